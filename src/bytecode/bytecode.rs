@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use bincode::SizeLimit;
+use bincode::rustc_serialize::{encode_into, decode_from};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use bytecode::{MAGIC, FORMAT_VERSION, MAX_SIZE};
+use bytecode::error::BytecodeError;
+use optimizer::OptimizerPass;
+use vm::{VirtualMachine, CellConfig};
+use vm::error::VmError;
+use vm::instruction::Instruction;
+use vm::opcodes::Opcode;
+
+/// The `Bytecode` type.
+///
+/// A lexed and optimized program, which can be executed
+/// without lexing and optimizing the source again.
+///
+/// The binary format consists of the magic header (see `MAGIC`),
+/// the format version (see `FORMAT_VERSION`) as a big-endian `u16`
+/// and the bincode-encoded `Bytecode`, which may take up to `MAX_SIZE` bytes.
+#[derive(Clone, RustcEncodable, RustcDecodable, PartialEq, Debug)]
+pub struct Bytecode {
+    /// The optimization passes applied to the instructions.
+    pub passes: Vec<OptimizerPass>,
+
+    /// The cell configuration the instructions were optimized for.
+    pub cell_config: CellConfig,
+
+    /// The instructions.
+    ///
+    /// The source positions are stored separately, see `debug_info`.
+    pub instructions: Vec<Instruction>,
+
+    /// The precomputed jump table.
+    pub jump_table: BTreeMap<usize, usize>,
+
+    /// The line and the position of every instruction; `None` if stripped.
+    pub debug_info: Option<Vec<(u32, u32)>>,
+}
+
+/// The `Bytecode` implementation.
+impl Bytecode {
+    /// Constructs a new `Bytecode`.
+    ///
+    /// Leaves out the passes, which do not support the cell configuration,
+    /// since the `Optimizer` skipped them.
+    /// Fails if the jump table cannot be built, e.g. because of unmatched loops.
+    pub fn new(instructions: Vec<Instruction>,
+               passes: Vec<OptimizerPass>,
+               cell_config: CellConfig,
+               debug_info: bool)
+               -> Result<Bytecode, VmError> {
+
+        // Build the jump table
+        let jump_table = VirtualMachine::build_jump_table(&instructions)?;
+
+        // Move the source positions into the debug information
+        let positions = instructions.iter().map(|instr| (instr.line, instr.pos)).collect();
+        let instructions = instructions.into_iter()
+            .map(|instr| Instruction { line: 0, pos: 0, ..instr })
+            .collect();
+
+        // Create the bytecode
+        Ok(Bytecode {
+            passes: passes.into_iter().filter(|pass| pass.supports(&cell_config)).collect(),
+            cell_config: cell_config,
+            instructions: instructions,
+            jump_table: jump_table,
+            debug_info: match debug_info {
+                true => Some(positions),
+                false => None,
+            },
+        })
+    }
+
+    /// Tests if the data starts with the magic header.
+    pub fn is_bytecode(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    /// Reads bytecode from a stream.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Bytecode, BytecodeError> {
+
+        // Read the header
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(BytecodeError::InvalidMagic);
+        }
+        let version = reader.read_u16::<NetworkEndian>()?;
+        if version != FORMAT_VERSION {
+            return Err(BytecodeError::UnsupportedVersion(version));
+        }
+
+        // Read the bytecode
+        let bytecode: Bytecode = match decode_from(reader, SizeLimit::Bounded(MAX_SIZE)) {
+            Ok(bytecode) => bytecode,
+            Err(err) => return Err(BytecodeError::Decoding(err.to_string())),
+        };
+        bytecode.validate()?;
+        Ok(bytecode)
+    }
+
+    /// Writes the bytecode to a stream.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), BytecodeError> {
+
+        // Write the header
+        writer.write_all(MAGIC)?;
+        writer.write_u16::<NetworkEndian>(FORMAT_VERSION)?;
+
+        // Write the bytecode
+        match encode_into(self, writer, SizeLimit::Bounded(MAX_SIZE)) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(BytecodeError::Encoding(err.to_string())),
+        }
+    }
+
+    /// Tests if the instructions behave as intended using the cell configuration.
+    ///
+    /// This is the case if every optimization pass applied supports it.
+    pub fn supports(&self, cell_config: &CellConfig) -> bool {
+        self.passes.iter().all(|pass| pass.supports(cell_config))
+    }
+
+    /// Gets the instructions.
+    ///
+    /// Restores the source positions, unless the debug information was stripped.
+    pub fn to_instructions(&self) -> Vec<Instruction> {
+        match self.debug_info {
+            Some(ref positions) => {
+                self.instructions
+                    .iter()
+                    .zip(positions.iter())
+                    .map(|(instr, &(line, pos))| {
+                        Instruction {
+                            line: line,
+                            pos: pos,
+                            ..instr.clone()
+                        }
+                    })
+                    .collect()
+            }
+            None => self.instructions.clone(),
+        }
+    }
+
+    /// Tests if the jump table and the debug information match the instructions.
+    ///
+    /// This protects the virtual machine against corrupted files.
+    fn validate(&self) -> Result<(), BytecodeError> {
+        let len = self.instructions.len();
+
+        // Test the debug information
+        if let Some(ref positions) = self.debug_info {
+            if positions.len() != len {
+                return Err(BytecodeError::Decoding("Invalid debug information".to_string()));
+            }
+        }
+
+        // Test the jump table
+        // Every loop boundary has to point at its counterpart, which points back at it.
+        // Like `VirtualMachine::build_jump_table`, any loop start matches any loop end.
+        // Every loop control instruction has to point into a loop around it:
+        // a break at the end of the loop, a stack jump right before it.
+        for (i, instr) in self.instructions.iter().enumerate() {
+            let target = self.jump_table.get(&i).cloned();
+            let valid = match (instr.opcode, target) {
+                (Opcode::JzCell, Some(end)) | (Opcode::JzStack, Some(end)) => {
+                    end > i && self.points_at(end, &[Opcode::JnzCell, Opcode::JnzStack], i)
+                }
+                (Opcode::JnzCell, Some(start)) | (Opcode::JnzStack, Some(start)) => {
+                    start < i && self.points_at(start, &[Opcode::JzCell, Opcode::JzStack], i)
+                }
+                (Opcode::Break, Some(end)) => end > i && self.encloses(end, i),
+                (Opcode::JmpStack, Some(end)) => {
+                    end >= i && self.encloses(end.saturating_add(1), i)
+                }
+                (Opcode::JzCell, None) | (Opcode::JnzCell, None) | (Opcode::JzStack, None) |
+                (Opcode::JnzStack, None) | (Opcode::Break, None) | (Opcode::JmpStack, None) => {
+                    false
+                }
+                _ => true,
+            };
+            if !valid {
+                return Err(BytecodeError::Decoding("Invalid jump table".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Tests if the instruction at `i` has one of the opcodes and jumps to `target`.
+    fn points_at(&self, i: usize, opcodes: &[Opcode], target: usize) -> bool {
+        self.instructions.get(i).map_or(false, |instr| opcodes.contains(&instr.opcode)) &&
+        self.jump_table.get(&i) == Some(&target)
+    }
+
+    /// Tests if the loop ending at `end` contains the instruction at `i`.
+    fn encloses(&self, end: usize, i: usize) -> bool {
+        match self.instructions.get(end).map(|instr| instr.opcode) {
+            Some(Opcode::JnzCell) | Some(Opcode::JnzStack) => {
+                self.jump_table.get(&end).map_or(false, |&start| start < i)
+            }
+            _ => false,
+        }
+    }
+}
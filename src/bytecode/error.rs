@@ -0,0 +1,57 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result};
+use std::io;
+
+/// The `BytecodeError` type.
+#[derive(Debug)]
+pub enum BytecodeError {
+    /// The data does not start with the magic header.
+    InvalidMagic,
+
+    /// The data was written using an unsupported version of the format.
+    UnsupportedVersion(u16),
+
+    /// The data could not be decoded.
+    Decoding(String),
+
+    /// The bytecode could not be encoded.
+    Encoding(String),
+
+    /// Reading from or writing to a stream failed.
+    Io(io::Error),
+}
+
+/// Implements the `Display` trait for the `BytecodeError` type.
+impl Display for BytecodeError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match *self {
+            BytecodeError::InvalidMagic => write!(f, "Not a qo bytecode file"),
+            BytecodeError::UnsupportedVersion(version) => {
+                write!(f,
+                       "Unsupported bytecode version {} (expected {})",
+                       version,
+                       super::FORMAT_VERSION)
+            }
+            BytecodeError::Decoding(ref err) => write!(f, "Unable to decode bytecode: {}", err),
+            BytecodeError::Encoding(ref err) => write!(f, "Unable to encode bytecode: {}", err),
+            BytecodeError::Io(ref err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+/// Implements the `Error` trait for the `BytecodeError` type.
+impl Error for BytecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            BytecodeError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Implements the `From<io::Error>` trait for the `BytecodeError` type.
+impl From<io::Error> for BytecodeError {
+    fn from(err: io::Error) -> BytecodeError {
+        BytecodeError::Io(err)
+    }
+}
@@ -0,0 +1,17 @@
+pub mod bytecode;
+pub mod error;
+pub use bytecode::bytecode::Bytecode;
+pub use bytecode::error::BytecodeError;
+
+/// The magic header every bytecode file starts with.
+pub const MAGIC: &'static [u8; 4] = b"QOC\0";
+
+/// The maximum size of the encoded `Bytecode` in bytes.
+///
+/// Protects against corrupted files, which claim to hold huge amounts of data.
+pub const MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The version of the bytecode format.
+///
+/// Increment this whenever the layout of `Bytecode`, `Instruction` or `Opcode` changes.
+pub const FORMAT_VERSION: u16 = 8;
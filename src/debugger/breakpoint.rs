@@ -0,0 +1,243 @@
+use std::fmt::{Display, Formatter, Result};
+use vm::VirtualMachine;
+
+/// The `Breakpoint` type.
+///
+/// Stops the execution once it is hit, if the condition holds.
+#[derive(Clone, RustcEncodable, RustcDecodable, PartialEq, Debug)]
+pub struct Breakpoint {
+    /// Decides when the breakpoint is hit. See `BreakpointKind` for details.
+    pub kind: BreakpointKind,
+
+    /// The condition; `None` if the breakpoint is unconditional.
+    pub condition: Option<Condition>,
+}
+
+/// The `BreakpointKind` type.
+#[derive(Clone, RustcEncodable, RustcDecodable, PartialEq, Debug)]
+pub enum BreakpointKind {
+    /// Hit when the execution reaches the line, or the position on the line.
+    ///
+    /// Uses the same line and position numbering as `Instruction`.
+    /// Hit again when the execution comes back to the line or the position,
+    /// either from another line or by jumping back, e.g. at the end of a loop.
+    Line(u32, Option<u32>),
+
+    /// Hit when the tick count reaches the value.
+    Ticks(u64),
+
+    /// Hit when the value of the cell at the index changes.
+    WatchCell(i64),
+
+    /// Hit when the depth of the stack changes.
+    WatchStack,
+}
+
+/// The `Condition` type.
+///
+/// Compares an operand to a value.
+#[derive(Copy, Clone, RustcEncodable, RustcDecodable, PartialEq, Eq, Debug)]
+pub struct Condition {
+    /// The operand.
+    pub operand: Operand,
+
+    /// The comparison.
+    pub comparison: Comparison,
+
+    /// The value the operand is compared to.
+    pub value: i64,
+}
+
+/// The `Operand` type.
+#[derive(Copy, Clone, RustcEncodable, RustcDecodable, PartialEq, Eq, Debug)]
+pub enum Operand {
+    /// The value of the cell at the cell pointer.
+    Cell,
+
+    /// The value of the cell at the index.
+    CellAt(i64),
+
+    /// The item on top of the stack; zero if the stack is empty.
+    StackTop,
+
+    /// The depth of the stack.
+    StackDepth,
+}
+
+/// The `Comparison` type.
+#[derive(Copy, Clone, RustcEncodable, RustcDecodable, PartialEq, Eq, Debug)]
+pub enum Comparison {
+    /// `==`
+    Equal,
+
+    /// `!=`
+    NotEqual,
+
+    /// `<`
+    Less,
+
+    /// `<=`
+    LessOrEqual,
+
+    /// `>`
+    Greater,
+
+    /// `>=`
+    GreaterOrEqual,
+}
+
+/// The `Breakpoint` implementation.
+impl Breakpoint {
+    /// Constructs a new unconditional `Breakpoint`.
+    pub fn new(kind: BreakpointKind) -> Breakpoint {
+        Breakpoint {
+            kind: kind,
+            condition: None,
+        }
+    }
+
+    /// Observes the state of the virtual machine.
+    ///
+    /// The breakpoint is hit when the observed value changes.
+    /// Line breakpoints observe the instruction index plus one while the line is reached
+    /// and zero otherwise, so that they are hit when the line is entered or jumped back to.
+    /// Tick breakpoints observe whether they are reached,
+    /// so that they are only hit when they become reached.
+    pub fn observe(&self, vm: &VirtualMachine) -> i64 {
+        match self.kind {
+            BreakpointKind::Line(line, pos) => {
+                match vm.current_instruction() {
+                    Some(instr) if instr.line == line && pos.map_or(true, |pos| instr.pos == pos) => {
+                        vm.ip() as i64 + 1
+                    }
+                    _ => 0,
+                }
+            }
+            BreakpointKind::Ticks(ticks) => (vm.ticks() >= ticks) as i64,
+            BreakpointKind::WatchCell(index) => vm.tape().get(index),
+            BreakpointKind::WatchStack => vm.stack().len() as i64,
+        }
+    }
+
+    /// Tests if the breakpoint is hit, given the previously observed value.
+    pub fn is_hit(&self, vm: &VirtualMachine, previous: i64, current: i64) -> bool {
+        let hit = match self.kind {
+            BreakpointKind::Line(..) => current != 0 && (previous == 0 || current <= previous),
+            BreakpointKind::Ticks(_) => previous == 0 && current == 1,
+            BreakpointKind::WatchCell(_) | BreakpointKind::WatchStack => previous != current,
+        };
+        hit && self.condition.map_or(true, |condition| condition.holds(vm))
+    }
+
+    /// Tests if the breakpoint is a watchpoint.
+    pub fn is_watchpoint(&self) -> bool {
+        match self.kind {
+            BreakpointKind::WatchCell(_) | BreakpointKind::WatchStack => true,
+            _ => false,
+        }
+    }
+}
+
+/// The `Condition` implementation.
+impl Condition {
+    /// Parses a condition.
+    ///
+    /// The syntax is `<operand> <comparison> <value>`, where the operand is
+    /// `cell`, `cell[<index>]`, `top` or `depth`,
+    /// and the comparison is one of `==`, `!=`, `<`, `<=`, `>` and `>=`.
+    pub fn parse(text: &str) -> Option<Condition> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.len() != 3 {
+            return None;
+        }
+        let operand = match words[0] {
+            "cell" => Operand::Cell,
+            "top" => Operand::StackTop,
+            "depth" => Operand::StackDepth,
+            word if word.starts_with("cell[") && word.ends_with(']') => {
+                match word[5..word.len() - 1].parse() {
+                    Ok(index) => Operand::CellAt(index),
+                    Err(_) => return None,
+                }
+            }
+            _ => return None,
+        };
+        let comparison = match words[1] {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessOrEqual,
+            ">" => Comparison::Greater,
+            ">=" => Comparison::GreaterOrEqual,
+            _ => return None,
+        };
+        match words[2].parse() {
+            Ok(value) => {
+                Some(Condition {
+                    operand: operand,
+                    comparison: comparison,
+                    value: value,
+                })
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Tests if the condition holds.
+    pub fn holds(&self, vm: &VirtualMachine) -> bool {
+        let operand = match self.operand {
+            Operand::Cell => vm.tape().get(vm.cp()),
+            Operand::CellAt(index) => vm.tape().get(index),
+            Operand::StackTop => vm.stack().last().cloned().unwrap_or(0),
+            Operand::StackDepth => vm.stack().len() as i64,
+        };
+        match self.comparison {
+            Comparison::Equal => operand == self.value,
+            Comparison::NotEqual => operand != self.value,
+            Comparison::Less => operand < self.value,
+            Comparison::LessOrEqual => operand <= self.value,
+            Comparison::Greater => operand > self.value,
+            Comparison::GreaterOrEqual => operand >= self.value,
+        }
+    }
+}
+
+/// Implements the `Display` trait for the `Breakpoint` type.
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self.kind {
+            BreakpointKind::Line(line, None) => write!(f, "line {}", line)?,
+            BreakpointKind::Line(line, Some(pos)) => write!(f, "line {}:{}", line, pos)?,
+            BreakpointKind::Ticks(ticks) => write!(f, "tick {}", ticks)?,
+            BreakpointKind::WatchCell(index) => write!(f, "watch cell[{}]", index)?,
+            BreakpointKind::WatchStack => write!(f, "watch stack")?,
+        }
+        match self.condition {
+            Some(ref condition) => write!(f, " if {}", condition),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Implements the `Display` trait for the `Condition` type.
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self.operand {
+            Operand::Cell => write!(f, "cell")?,
+            Operand::CellAt(index) => write!(f, "cell[{}]", index)?,
+            Operand::StackTop => write!(f, "top")?,
+            Operand::StackDepth => write!(f, "depth")?,
+        }
+        write!(f,
+               " {} {}",
+               match self.comparison {
+                   Comparison::Equal => "==",
+                   Comparison::NotEqual => "!=",
+                   Comparison::Less => "<",
+                   Comparison::LessOrEqual => "<=",
+                   Comparison::Greater => ">",
+                   Comparison::GreaterOrEqual => ">=",
+               },
+               self.value)
+    }
+}
@@ -1,12 +1,17 @@
-use std::io::{Read, Cursor};
-use std::net::TcpStream;
-use bincode::rustc_serialize::decode;
-use byteorder::{NetworkEndian, ReadBytesExt};
-use super::DebugInformation;
+use std::cmp::max;
+use std::thread;
+use std::time::{Duration, Instant};
+use debugger::{DebugCommand, DebugResponse};
+use debugger::error::DebugError;
+use debugger::protocol;
+use debugger::transport::{DebugAddress, Stream};
+
+/// The time between two connection attempts.
+const RETRY_INTERVAL_MS: u64 = 100;
 
 /// The `Client` type.
 pub struct Client {
-    stream: Option<TcpStream>,
+    stream: Option<Stream>,
 }
 
 /// The `Client` implementation.
@@ -16,36 +21,57 @@ impl Client {
         Client { stream: None }
     }
 
-    /// Attempts to connect to a debug server.
-    pub fn connect(&mut self) -> bool {
-        self.stream = TcpStream::connect(("127.0.0.1", super::DEBUGGER_PORT)).ok();
-        self.stream.is_some()
+    /// Connects to a debug server.
+    ///
+    /// Failed attempts are retried until the timeout elapses,
+    /// which allows starting the client before the server.
+    pub fn connect(&mut self, address: &DebugAddress, timeout: Duration) -> Result<(), DebugError> {
+        let retry_interval = Duration::from_millis(RETRY_INTERVAL_MS);
+        let started = Instant::now();
+        loop {
+
+            // Attempt to connect
+            // A single attempt takes at most the remaining time, or the retry interval.
+            let remaining = timeout.checked_sub(started.elapsed()).unwrap_or_default();
+            let err = match Stream::connect(address, max(remaining, retry_interval)) {
+                Ok(stream) => {
+                    self.stream = Some(stream);
+                    return Ok(());
+                }
+                Err(err) => err,
+            };
+
+            // Give up once the timeout elapses
+            if started.elapsed() + retry_interval > timeout {
+                return Err(DebugError::Connect(address.to_string(), err));
+            }
+            thread::sleep(retry_interval);
+        }
     }
 
-    /// Attempts to receive updates from the server.
-    pub fn get_update(&mut self) -> Option<DebugInformation> {
-        // Return `Option::None` if no connection is active.
-        if self.stream.is_none() {
-            return None;
+    /// Creates a second handle to the same connection.
+    ///
+    /// This allows sending commands on one thread, while receiving responses on another.
+    pub fn try_clone(&self) -> Result<Client, DebugError> {
+        match self.stream {
+            Some(ref stream) => Ok(Client { stream: Some(stream.try_clone()?) }),
+            None => Err(DebugError::NotConnected),
         }
+    }
 
-        // Get the stream
-        let stream = self.stream.as_mut().unwrap();
+    /// Sends a command to the server.
+    pub fn send(&mut self, command: &DebugCommand) -> Result<(), DebugError> {
+        match self.stream {
+            Some(ref mut stream) => protocol::send(stream, command),
+            None => Err(DebugError::NotConnected),
+        }
+    }
 
-        // Read the length prefix
-        let mut length_prefix_buffer = vec![0u8; 4];
-        match stream.read_exact(length_prefix_buffer.as_mut_slice()).ok() {
-            None => return None,
-            _ => (),
+    /// Receives a response from the server; blocks until one is received.
+    pub fn receive(&mut self) -> Result<DebugResponse, DebugError> {
+        match self.stream {
+            Some(ref mut stream) => protocol::receive(stream),
+            None => Err(DebugError::NotConnected),
         }
-        let mut reader = Cursor::new(&length_prefix_buffer);
-        let length_prefix = reader.read_u32::<NetworkEndian>()
-            .expect("Unable to read length prefix as u32.");
-
-        // Read the data
-        let mut raw_data: Vec<u8> = vec![0u8; length_prefix as usize];
-        stream.read_exact(&mut raw_data).expect("Unable to read data.");
-        let data: Option<DebugInformation> = decode(&mut raw_data).ok();
-        data
     }
 }
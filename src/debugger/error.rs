@@ -0,0 +1,80 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result};
+use std::io;
+
+/// The `DebugError` type.
+#[derive(Debug)]
+pub enum DebugError {
+    /// No connection is active.
+    NotConnected,
+
+    /// The server could not be bound to the address.
+    Bind(String, io::Error),
+
+    /// No connection to the address could be established.
+    Connect(String, io::Error),
+
+    /// The other side closed the connection.
+    Disconnected,
+
+    /// The message was sent using an unsupported version of the protocol.
+    UnsupportedVersion(u16),
+
+    /// The message could not be decoded.
+    Decoding(String),
+
+    /// The message could not be encoded.
+    Encoding(String),
+
+    /// Reading from or writing to the connection failed.
+    Io(io::Error),
+}
+
+/// Implements the `Display` trait for the `DebugError` type.
+impl Display for DebugError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match *self {
+            DebugError::NotConnected => write!(f, "Not connected"),
+            DebugError::Bind(ref address, ref err) => {
+                write!(f, "Unable to bind to {}: {}", address, err)
+            }
+            DebugError::Connect(ref address, ref err) => {
+                write!(f, "Unable to connect to {}: {}", address, err)
+            }
+            DebugError::Disconnected => write!(f, "Connection closed"),
+            DebugError::UnsupportedVersion(version) => {
+                write!(f,
+                       "Unsupported protocol version {} (expected {})",
+                       version,
+                       super::PROTOCOL_VERSION)
+            }
+            DebugError::Decoding(ref err) => write!(f, "Unable to decode message: {}", err),
+            DebugError::Encoding(ref err) => write!(f, "Unable to encode message: {}", err),
+            DebugError::Io(ref err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+/// Implements the `Error` trait for the `DebugError` type.
+impl Error for DebugError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DebugError::Bind(_, ref err) |
+            DebugError::Connect(_, ref err) |
+            DebugError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Implements the `From<io::Error>` trait for the `DebugError` type.
+///
+/// Reaching the end of the stream means that the other side closed the connection.
+impl From<io::Error> for DebugError {
+    fn from(err: io::Error) -> DebugError {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => DebugError::Disconnected,
+            _ => DebugError::Io(err),
+        }
+    }
+}
@@ -1,8 +1,16 @@
 mod server;
 mod client;
+pub mod error;
+pub mod protocol;
+pub mod breakpoint;
+mod transport;
 use debugger::server::Server;
 use debugger::client::Client;
+use vm::PrintMode;
 use vm::instruction::Instruction;
+pub use debugger::error::DebugError;
+pub use debugger::transport::DebugAddress;
+pub use debugger::breakpoint::{Breakpoint, BreakpointKind, Condition, Operand, Comparison};
 
 /// The `DebugServer` type.
 pub type DebugServer = Server;
@@ -10,15 +18,212 @@ pub type DebugServer = Server;
 /// The `DebugClient` type.
 pub type DebugClient = Client;
 
-/// The port used by the debugger for communication.
+/// The port used by the debugger for communication, unless another address is given.
 pub const DEBUGGER_PORT: u16 = 38100;
 
+/// The maximum distance of the cells queried by `DebugCommand::GetMemory`.
+pub const MAX_MEMORY_DISTANCE: u32 = 1024;
+
+/// The maximum length of a message frame in bytes, including the protocol version.
+///
+/// Larger frames are rejected before they are read.
+pub const MAX_FRAME_LENGTH: u32 = 64 * 1024 * 1024;
+
+/// The version of the debugger protocol.
+///
+/// Increment this whenever the layout of `DebugCommand`, `DebugResponse`
+/// or `DebugInformation` changes.
+pub const PROTOCOL_VERSION: u16 = 4;
+
+/// The `DebugCommand` type.
+///
+/// Sent by the client to control the execution.
+#[derive(Clone, RustcEncodable, RustcDecodable, PartialEq, Debug)]
+pub enum DebugCommand {
+    /// Stops before the next instruction.
+    Pause,
+
+    /// Resumes the execution until the program is paused.
+    Continue,
+
+    /// Executes a single instruction.
+    Step,
+
+    /// Executes a whole loop, if the current instruction starts one.
+    /// Otherwise, executes a single instruction.
+    StepOverLoop,
+
+    /// Resumes the execution until an instruction on the line is reached.
+    RunToLine(u32),
+
+    /// Adds a breakpoint or a watchpoint.
+    SetBreakpoint(Breakpoint),
+
+    /// Removes the breakpoint or the watchpoint with the id.
+    RemoveBreakpoint(u32),
+
+    /// Lists the breakpoints and the watchpoints.
+    ListBreakpoints,
+
+    /// Queries the registers.
+    GetRegisters,
+
+    /// Queries the cells within the distance of the cell pointer.
+    ///
+    /// The distance is capped at `MAX_MEMORY_DISTANCE`.
+    GetMemory(u32),
+
+    /// Queries the stack.
+    GetStack,
+
+    /// Sets the value of the cell at the index.
+    SetCell(i64, i64),
+
+    /// Sets the value of the stack item at the index, counted from the bottom of the stack.
+    SetStackItem(usize, i64),
+
+    /// Queries the value of the cell at the index.
+    GetCell(i64),
+
+    /// Queries the source the instructions were lexed from.
+    GetSource,
+}
+
+/// The `DebugResponse` type.
+///
+/// Sent by the server in response to a `DebugCommand`.
+#[derive(Clone, RustcEncodable, RustcDecodable, PartialEq, Debug)]
+pub enum DebugResponse {
+    /// The execution was resumed.
+    ///
+    /// Followed by `Stopped` once the execution stops again.
+    Running,
+
+    /// The execution stopped; the server waits for the next command.
+    Stopped(DebugInformation),
+
+    /// The breakpoint was added with the id.
+    BreakpointSet(u32),
+
+    /// The breakpoint with the id was removed.
+    BreakpointRemoved(u32),
+
+    /// The breakpoints along with their ids, ordered by id.
+    Breakpoints(Vec<(u32, Breakpoint)>),
+
+    /// The registers.
+    Registers(Registers),
+
+    /// The requested cells.
+    Memory(MemoryWindow),
+
+    /// The stack; the last item is the top of the stack.
+    Stack(Vec<i64>),
+
+    /// The cell or the stack item was set.
+    Modified,
+
+    /// The source; `None` if the instructions were not lexed from a source file.
+    Source(Option<Source>),
+
+    /// The command was rejected with the error message.
+    Error(String),
+}
+
+/// The `StopReason` type.
+#[derive(Clone, RustcEncodable, RustcDecodable, PartialEq, Debug)]
+pub enum StopReason {
+    /// The program is about to execute the first instruction.
+    Entry,
+
+    /// The execution was paused by the client.
+    Pause,
+
+    /// A step finished.
+    Step,
+
+    /// An instruction on the requested line was reached.
+    Line(u32),
+
+    /// The breakpoint with the id was hit.
+    Breakpoint(u32),
+
+    /// The watchpoint with the id was hit; contains the previous and the current value.
+    Watchpoint(u32, i64, i64),
+
+    /// The program has halted with the exit code.
+    Halted(i32),
+
+    /// The program was aborted with the error message.
+    Error(String),
+}
+
+/// The `Registers` type.
+#[derive(Clone, RustcEncodable, RustcDecodable, PartialEq, Debug)]
+pub struct Registers {
+    /// The instruction pointer.
+    pub ip: usize,
+
+    /// The cell pointer.
+    pub cp: i64,
+
+    /// The ticks; more specifically the cycle count.
+    pub ticks: u64,
+
+    /// The print mode.
+    pub print_mode: PrintMode,
+
+    /// The depth of the stack.
+    pub stack_depth: usize,
+}
+
+/// The `MemoryWindow` type.
+///
+/// A range of cells around the cell pointer.
+#[derive(Clone, RustcEncodable, RustcDecodable, PartialEq, Debug)]
+pub struct MemoryWindow {
+    /// The cell pointer.
+    pub cp: i64,
+
+    /// The index of the first cell.
+    pub start: i64,
+
+    /// The values of the cells.
+    pub cells: Vec<i64>,
+}
+
+/// The `Source` type.
+#[derive(Clone, RustcEncodable, RustcDecodable, PartialEq, Debug)]
+pub struct Source {
+    /// The name of the source file.
+    pub name: String,
+
+    /// The contents of the source file.
+    pub text: String,
+}
+
 /// The `DebugInformation` type.
-#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+#[derive(Clone, RustcEncodable, RustcDecodable, PartialEq, Debug)]
 pub struct DebugInformation {
-    /// The current instruction.
+    /// The instruction to be executed next; `None` if the program has halted.
     pub instr: Option<Instruction>,
 
-    /// Whether the debugger should terminate.
-    pub terminate: bool,
+    /// The instruction pointer.
+    pub ip: usize,
+
+    /// The reason why the execution stopped.
+    pub reason: StopReason,
+}
+
+/// The `DebugInformation` implementation.
+impl DebugInformation {
+    /// Tests if the debugging session is over.
+    ///
+    /// The server does not accept any further commands in that case.
+    pub fn is_terminated(&self) -> bool {
+        match self.reason {
+            StopReason::Halted(_) | StopReason::Error(_) => true,
+            _ => false,
+        }
+    }
 }
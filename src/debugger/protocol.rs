@@ -0,0 +1,61 @@
+use std::io::{Read, Write};
+use bincode::SizeLimit;
+use bincode::rustc_serialize::{encode, decode};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use rustc_serialize::{Encodable, Decodable};
+use debugger::{PROTOCOL_VERSION, MAX_FRAME_LENGTH};
+use debugger::error::DebugError;
+
+/// Sends a message.
+///
+/// Every message is framed by a big-endian `u32` length prefix,
+/// followed by the protocol version (see `PROTOCOL_VERSION`) as a big-endian `u16`
+/// and the bincode-encoded message. The length prefix includes the version
+/// and may not exceed `MAX_FRAME_LENGTH`.
+pub fn send<W: Write, T: Encodable>(writer: &mut W, message: &T) -> Result<(), DebugError> {
+
+    // Encode the message
+    let data = match encode(message, SizeLimit::Bounded(MAX_FRAME_LENGTH as u64 - 2)) {
+        Ok(data) => data,
+        Err(err) => return Err(DebugError::Encoding(err.to_string())),
+    };
+
+    // Write the frame
+    // The frame is written at once, so that it is never interleaved with other frames.
+    let mut frame = Vec::with_capacity(data.len() + 6);
+    frame.write_u32::<NetworkEndian>(data.len() as u32 + 2)?;
+    frame.write_u16::<NetworkEndian>(PROTOCOL_VERSION)?;
+    frame.extend_from_slice(&data);
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Receives a message.
+///
+/// Blocks until a complete message has been received. See `send` for the framing.
+pub fn receive<R: Read, T: Decodable>(reader: &mut R) -> Result<T, DebugError> {
+
+    // Read the frame header
+    let length = reader.read_u32::<NetworkEndian>()?;
+    if length < 2 {
+        return Err(DebugError::Decoding("Invalid length prefix".to_string()));
+    }
+    if length > MAX_FRAME_LENGTH {
+        return Err(DebugError::Decoding("Frame too large".to_string()));
+    }
+    let version = reader.read_u16::<NetworkEndian>()?;
+
+    // Read the message
+    // The message is read even if the version is unsupported,
+    // so that the stream stays in sync with the frames.
+    let mut data = vec![0u8; length as usize - 2];
+    reader.read_exact(&mut data)?;
+    if version != PROTOCOL_VERSION {
+        return Err(DebugError::UnsupportedVersion(version));
+    }
+    match decode(&data) {
+        Ok(message) => Ok(message),
+        Err(err) => Err(DebugError::Decoding(err.to_string())),
+    }
+}
@@ -1,51 +1,352 @@
-use std::io::Write;
-use std::net::{TcpStream, TcpListener, SocketAddr};
-use bincode::SizeLimit;
-use bincode::rustc_serialize::encode;
-use byteorder::{NetworkEndian, WriteBytesExt};
-use super::DebugInformation;
+use std::cmp::min;
+use std::collections::BTreeMap;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use debugger::{DebugCommand, DebugResponse, DebugInformation, StopReason};
+use debugger::{Registers, MemoryWindow, MAX_MEMORY_DISTANCE};
+use debugger::breakpoint::{Breakpoint, BreakpointKind};
+use debugger::error::DebugError;
+use debugger::protocol;
+use debugger::transport::{DebugAddress, Listener, Stream};
+use vm::VirtualMachine;
+use vm::error::VmError;
+use vm::opcodes::Opcode;
+
+/// The `Mode` type.
+///
+/// Decides when the execution stops.
+enum Mode {
+    /// Stop before the next instruction.
+    Stop(StopReason),
+
+    /// Run until paused by the client.
+    Run,
+
+    /// Run until the instruction pointer reaches the position.
+    RunToPosition(usize),
+
+    /// Run until an instruction on the line is reached.
+    RunToLine(u32),
+}
 
 /// The `Server` type.
 pub struct Server {
+    /// The listener; `None` once a client connected.
+    listener: Option<Listener>,
+
     /// The connected client.
-    pub client: Option<(TcpStream, SocketAddr)>,
+    client: Option<Stream>,
+
+    /// The commands received from the client.
+    commands: Option<Receiver<Result<DebugCommand, DebugError>>>,
+
+    /// The execution mode. See `Mode` for details.
+    mode: Mode,
+
+    /// The breakpoints along with the value they observed before the last instruction.
+    breakpoints: BTreeMap<u32, (Breakpoint, i64)>,
+
+    /// The id of the next breakpoint.
+    next_breakpoint: u32,
 }
 
 /// The `Server` implementation.
 impl Server {
-    /// Constructs a new `Server`.
-    pub fn new() -> Server {
-        Server { client: None }
+    /// Binds a new `Server` to the address.
+    ///
+    /// The execution stops before the first instruction,
+    /// so that the client can prepare the debugging session.
+    pub fn bind(address: &DebugAddress) -> Result<Server, DebugError> {
+        let listener = match Listener::bind(address) {
+            Ok(listener) => listener,
+            Err(err) => return Err(DebugError::Bind(address.to_string(), err)),
+        };
+        Ok(Server {
+            listener: Some(listener),
+            client: None,
+            commands: None,
+            mode: Mode::Stop(StopReason::Entry),
+            breakpoints: BTreeMap::new(),
+            next_breakpoint: 1,
+        })
+    }
+
+    /// Gets the address the server is bound to; `None` once a client connected.
+    pub fn address(&self) -> Option<DebugAddress> {
+        self.listener.as_ref().map(Listener::address)
+    }
+
+    /// Accepts a connection; blocks until a client connects.
+    ///
+    /// The server stops listening afterwards, which frees the address for other sessions.
+    pub fn accept(&mut self) -> Result<(), DebugError> {
+        let stream = match self.listener.take() {
+            Some(listener) => listener.accept()?,
+            None => return Err(DebugError::NotConnected),
+        };
+        let mut reader = stream.try_clone()?;
+
+        // Receive the commands on a separate thread
+        // This allows the virtual machine to test for commands without blocking.
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || loop {
+            let command = protocol::receive(&mut reader);
+            let failed = command.is_err();
+            if sender.send(command).is_err() || failed {
+                break;
+            }
+        });
+        self.client = Some(stream);
+        self.commands = Some(receiver);
+        Ok(())
+    }
+
+    /// Sends a response to the connected client.
+    pub fn send(&mut self, response: &DebugResponse) -> Result<(), DebugError> {
+        match self.client {
+            Some(ref mut stream) => protocol::send(stream, response),
+            None => Err(DebugError::NotConnected),
+        }
+    }
+
+    /// Controls the execution.
+    ///
+    /// Called by the virtual machine before every instruction.
+    /// Blocks while the execution is stopped.
+    pub fn debug(&mut self, vm: &mut VirtualMachine) -> Result<(), DebugError> {
+
+        // Handle the pending commands
+        while let Some(command) = self.poll()? {
+            self.handle(command, vm, None)?;
+        }
+
+        // Test the breakpoints
+        // Every breakpoint observes every instruction, even while the execution stops anyway,
+        // so that the same change is never reported twice.
+        let mut hit = None;
+        for (&id, &mut (ref breakpoint, ref mut previous)) in self.breakpoints.iter_mut() {
+            let current = breakpoint.observe(vm);
+            if hit.is_none() && breakpoint.is_hit(vm, *previous, current) {
+                hit = Some(match breakpoint.is_watchpoint() {
+                    true => StopReason::Watchpoint(id, *previous, current),
+                    false => StopReason::Breakpoint(id),
+                });
+            }
+            *previous = current;
+        }
+
+        // Test if the execution should stop
+        // Breakpoints take precedence over the execution mode.
+        let reason = match self.mode {
+            Mode::Stop(ref reason) => Some(reason.clone()),
+            Mode::Run => None,
+            Mode::RunToPosition(ip) => {
+                match vm.ip() == ip {
+                    true => Some(StopReason::Step),
+                    false => None,
+                }
+            }
+            Mode::RunToLine(line) => {
+                match vm.current_instruction() {
+                    Some(instr) if instr.line == line => Some(StopReason::Line(line)),
+                    _ => None,
+                }
+            }
+        };
+        let info = match hit.or(reason) {
+            Some(reason) => Server::information(vm, reason),
+            None => return Ok(()),
+        };
+
+        // Wait for a command, which resumes the execution
+        self.send(&DebugResponse::Stopped(info.clone()))?;
+        loop {
+            let command = self.wait()?;
+            if self.handle(command, vm, Some(&info))? {
+                return Ok(());
+            }
+        }
     }
 
-    /// Binds the socket and accepts a connection.
-    pub fn bind_and_accept(&mut self) -> bool {
-        let sock = TcpListener::bind(("127.0.0.1", super::DEBUGGER_PORT)).unwrap();
-        self.client = sock.accept().ok();
-        self.client.is_some()
+    /// Reports the end of the program to the connected client.
+    pub fn terminate(&mut self,
+                     vm: &VirtualMachine,
+                     result: &Result<i32, VmError>)
+                     -> Result<(), DebugError> {
+        let reason = match *result {
+            Ok(code) => StopReason::Halted(code),
+            Err(ref err) => StopReason::Error(err.to_string()),
+        };
+        self.send(&DebugResponse::Stopped(Server::information(vm, reason)))
     }
 
-    /// Updates the debug information.
+    /// Handles a command.
     ///
-    /// In other words, sends the most recent
-    /// debugging information to the connected client.
-    pub fn update(&mut self, data: DebugInformation) -> bool {
-        // Get the client tcp stream
-        let tpl = self.client.as_mut().unwrap();
-        let mut sock: &TcpStream = &tpl.0;
-
-        // Encode and send the debug information
-        let mut length_prefix = vec![];
-        let mut encoded_data: Vec<u8> = encode(&data, SizeLimit::Infinite).unwrap();
-        length_prefix.write_u32::<NetworkEndian>(encoded_data.len() as u32).unwrap();
-        match sock.write(length_prefix.as_mut_slice()).ok() {
-            None => return false,
-            _ => (),
-        }
-        match sock.write(encoded_data.as_mut_slice()).ok() {
-            None => return false,
-            _ => (),
-        }
-        sock.flush().ok().is_some()
+    /// `stopped` contains the information sent to the client, if the execution is stopped.
+    /// Returns `true` if the command resumed the execution.
+    fn handle(&mut self,
+              command: DebugCommand,
+              vm: &mut VirtualMachine,
+              stopped: Option<&DebugInformation>)
+              -> Result<bool, DebugError> {
+        let mode = match command {
+
+            // Stop the execution
+            // If it is already stopped, the information is sent again.
+            DebugCommand::Pause => {
+                match stopped {
+                    Some(info) => self.send(&DebugResponse::Stopped(info.clone()))?,
+                    None => self.mode = Mode::Stop(StopReason::Pause),
+                }
+                return Ok(false);
+            }
+
+            // Resume the execution
+            DebugCommand::Continue => Mode::Run,
+            DebugCommand::Step => Mode::Stop(StopReason::Step),
+            DebugCommand::StepOverLoop => {
+                match vm.current_instruction().map(|instr| instr.opcode) {
+                    Some(Opcode::JzCell) | Some(Opcode::JzStack) => {
+                        Mode::RunToPosition(vm.jump_table()[&vm.ip()] + 1)
+                    }
+                    _ => Mode::Stop(StopReason::Step),
+                }
+            }
+            DebugCommand::RunToLine(line) => Mode::RunToLine(line),
+
+            // Manage the breakpoints
+            DebugCommand::SetBreakpoint(breakpoint) => {
+                let response = self.add_breakpoint(breakpoint, vm);
+                self.send(&response)?;
+                return Ok(false);
+            }
+            DebugCommand::RemoveBreakpoint(id) => {
+                let response = match self.breakpoints.remove(&id) {
+                    Some(_) => DebugResponse::BreakpointRemoved(id),
+                    None => DebugResponse::Error(format!("No breakpoint {}", id)),
+                };
+                self.send(&response)?;
+                return Ok(false);
+            }
+            DebugCommand::ListBreakpoints => {
+                let breakpoints = self.breakpoints
+                    .iter()
+                    .map(|(&id, &(ref breakpoint, _))| (id, breakpoint.clone()))
+                    .collect();
+                self.send(&DebugResponse::Breakpoints(breakpoints))?;
+                return Ok(false);
+            }
+
+            // Inspect or modify the state
+            command => {
+                let response = Server::inspect(command, vm);
+                self.send(&response)?;
+                return Ok(false);
+            }
+        };
+        self.mode = mode;
+        self.send(&DebugResponse::Running)?;
+        Ok(true)
+    }
+
+    /// Adds a breakpoint.
+    ///
+    /// Line breakpoints are rejected, unless an instruction is located at the line.
+    fn add_breakpoint(&mut self, breakpoint: Breakpoint, vm: &VirtualMachine) -> DebugResponse {
+        if let BreakpointKind::Line(line, pos) = breakpoint.kind {
+            let found = vm.instructions()
+                .iter()
+                .any(|instr| instr.line == line && pos.map_or(true, |pos| instr.pos == pos));
+            if !found {
+                return DebugResponse::Error(format!("No instruction at {}", breakpoint));
+            }
+        }
+        let id = self.next_breakpoint;
+        let observed = breakpoint.observe(vm);
+        self.breakpoints.insert(id, (breakpoint, observed));
+        self.next_breakpoint += 1;
+        DebugResponse::BreakpointSet(id)
+    }
+
+    /// Handles a command, which inspects or modifies the state of the virtual machine.
+    fn inspect(command: DebugCommand, vm: &mut VirtualMachine) -> DebugResponse {
+        match command {
+            DebugCommand::GetRegisters => {
+                DebugResponse::Registers(Registers {
+                    ip: vm.ip(),
+                    cp: vm.cp(),
+                    ticks: vm.ticks(),
+                    print_mode: vm.print_mode(),
+                    stack_depth: vm.stack().len(),
+                })
+            }
+            DebugCommand::GetMemory(distance) => {
+                let distance = min(distance, MAX_MEMORY_DISTANCE) as i64;
+                let start = vm.cp().saturating_sub(distance);
+                let end = vm.cp().saturating_add(distance + 1);
+                DebugResponse::Memory(MemoryWindow {
+                    cp: vm.cp(),
+                    start: start,
+                    cells: vm.tape().window(start, end),
+                })
+            }
+            DebugCommand::GetCell(index) => {
+                DebugResponse::Memory(MemoryWindow {
+                    cp: vm.cp(),
+                    start: index,
+                    cells: vec![vm.tape().get(index)],
+                })
+            }
+            DebugCommand::GetStack => DebugResponse::Stack(vm.stack().to_vec()),
+            DebugCommand::GetSource => DebugResponse::Source(vm.source().cloned()),
+            DebugCommand::SetCell(index, value) => {
+                match vm.write_cell(index, value) {
+                    Ok(_) => DebugResponse::Modified,
+                    Err(err) => DebugResponse::Error(err.to_string()),
+                }
+            }
+            DebugCommand::SetStackItem(index, value) => {
+                match index < vm.stack().len() {
+                    true => {
+                        match vm.write_stack(index, value) {
+                            Ok(_) => DebugResponse::Modified,
+                            Err(err) => DebugResponse::Error(err.to_string()),
+                        }
+                    }
+                    false => DebugResponse::Error(format!("No stack item {}", index)),
+                }
+            }
+            command => DebugResponse::Error(format!("Unexpected command {:?}", command)),
+        }
+    }
+
+    /// Receives a command, if one is pending.
+    fn poll(&mut self) -> Result<Option<DebugCommand>, DebugError> {
+        let commands = match self.commands {
+            Some(ref commands) => commands,
+            None => return Err(DebugError::NotConnected),
+        };
+        match commands.try_recv() {
+            Ok(command) => command.map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(DebugError::Disconnected),
+        }
+    }
+
+    /// Receives a command; blocks until one is received.
+    fn wait(&mut self) -> Result<DebugCommand, DebugError> {
+        match self.commands {
+            Some(ref commands) => commands.recv().unwrap_or(Err(DebugError::Disconnected)),
+            None => Err(DebugError::NotConnected),
+        }
+    }
+
+    /// Creates a `DebugInformation` describing the state of the virtual machine.
+    fn information(vm: &VirtualMachine, reason: StopReason) -> DebugInformation {
+        DebugInformation {
+            instr: vm.current_instruction().cloned(),
+            ip: vm.ip(),
+            reason: reason,
+        }
     }
 }
@@ -0,0 +1,304 @@
+use std::fmt::{self, Display, Formatter};
+#[cfg(unix)]
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Duration;
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::process;
+#[cfg(unix)]
+use libc;
+
+/// The `DebugAddress` type.
+///
+/// The address the debug server listens on.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DebugAddress {
+    /// A TCP socket, given as `host:port`.
+    Tcp(String),
+
+    /// A Unix domain socket, given as the path of the socket file.
+    Unix(PathBuf),
+}
+
+/// The `Listener` type.
+///
+/// Accepts a connection on a `DebugAddress`.
+pub enum Listener {
+    /// A TCP listener.
+    Tcp(TcpListener),
+
+    /// A Unix domain socket listener, along with the path of the socket file.
+    ///
+    /// The socket file is guarded by a lock file, see `Listener::bind`.
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+/// The `Stream` type.
+///
+/// A connection between the debug server and a client.
+pub enum Stream {
+    /// A TCP connection.
+    Tcp(TcpStream),
+
+    /// A Unix domain socket connection.
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+/// The `DebugAddress` implementation.
+impl DebugAddress {
+    /// Parses an address.
+    ///
+    /// Paths containing a `/` and addresses starting with `unix:` are Unix domain sockets.
+    /// A port on its own refers to the local host, everything else is a `host:port` pair.
+    ///
+    /// Paths without a `/`, e.g. `dbg.sock`, are taken for a host;
+    /// use `unix:dbg.sock` or `./dbg.sock` instead.
+    pub fn parse(text: &str) -> DebugAddress {
+        if text.starts_with("unix:") {
+            DebugAddress::Unix(PathBuf::from(&text[5..]))
+        } else if text.contains('/') {
+            DebugAddress::Unix(PathBuf::from(text))
+        } else if !text.is_empty() && text.chars().all(|chr| chr.is_digit(10)) {
+            DebugAddress::Tcp(format!("127.0.0.1:{}", text))
+        } else {
+            DebugAddress::Tcp(text.to_string())
+        }
+    }
+}
+
+/// Implements the `Default` trait for the `DebugAddress` type.
+///
+/// The default address is `DEBUGGER_PORT` on the local host.
+impl Default for DebugAddress {
+    fn default() -> DebugAddress {
+        DebugAddress::Tcp(format!("127.0.0.1:{}", super::DEBUGGER_PORT))
+    }
+}
+
+/// Implements the `Display` trait for the `DebugAddress` type.
+impl Display for DebugAddress {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            DebugAddress::Tcp(ref address) => write!(f, "{}", address),
+            DebugAddress::Unix(ref path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// The `Listener` implementation.
+impl Listener {
+    /// Binds a listener to the address.
+    ///
+    /// Unix domain sockets are guarded by a lock file next to the socket file,
+    /// e.g. `dbg.sock.lock`, which holds the id of the process listening on it.
+    /// A socket file left behind by a process, which is no longer running, is replaced.
+    /// Any other file is left untouched.
+    pub fn bind(address: &DebugAddress) -> io::Result<Listener> {
+        match *address {
+            DebugAddress::Tcp(ref address) => Ok(Listener::Tcp(TcpListener::bind(address.as_str())?)),
+            DebugAddress::Unix(ref path) => Listener::bind_unix(path),
+        }
+    }
+
+    #[cfg(unix)]
+    fn bind_unix(path: &PathBuf) -> io::Result<Listener> {
+
+        // Take the lock
+        // A lock left behind by a process, which is no longer running, is taken over.
+        let lock = lock_path(path);
+        let mut file = match create_lock(&lock) {
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                if let Some(pid) = lock_owner(&lock) {
+                    let message = format!("{} is in use by process {}", path.display(), pid);
+                    return Err(io::Error::new(io::ErrorKind::AddrInUse, message));
+                }
+                fs::remove_file(&lock)?;
+                create_lock(&lock)?
+            }
+            result => result?,
+        };
+
+        // Replace the socket file left behind by the previous owner of the lock
+        let result = file.write_all(process::id().to_string().as_bytes()).and_then(|_| {
+            if let Ok(metadata) = fs::symlink_metadata(path) {
+                if metadata.file_type().is_socket() {
+                    fs::remove_file(path)?;
+                }
+            }
+            UnixListener::bind(path)
+        });
+        match result {
+            Ok(listener) => Ok(Listener::Unix(listener, path.clone())),
+            Err(err) => {
+                fs::remove_file(&lock).ok();
+                Err(err)
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn bind_unix(_: &PathBuf) -> io::Result<Listener> {
+        Err(unsupported())
+    }
+
+    /// Accepts a connection; blocks until a client connects.
+    pub fn accept(&self) -> io::Result<Stream> {
+        match *self {
+            Listener::Tcp(ref listener) => Ok(Stream::Tcp(listener.accept()?.0)),
+            #[cfg(unix)]
+            Listener::Unix(ref listener, _) => Ok(Stream::Unix(listener.accept()?.0)),
+        }
+    }
+
+    /// Gets the address the listener is bound to.
+    ///
+    /// Differs from the requested address if the port was chosen by the system.
+    pub fn address(&self) -> DebugAddress {
+        match *self {
+            Listener::Tcp(ref listener) => {
+                match listener.local_addr() {
+                    Ok(address) => DebugAddress::Tcp(address.to_string()),
+                    Err(_) => DebugAddress::default(),
+                }
+            }
+            #[cfg(unix)]
+            Listener::Unix(_, ref path) => DebugAddress::Unix(path.clone()),
+        }
+    }
+}
+
+/// Implements the `Drop` trait for the `Listener` type.
+///
+/// Removes the Unix domain socket file and its lock file.
+impl Drop for Listener {
+    fn drop(&mut self) {
+        match *self {
+            #[cfg(unix)]
+            Listener::Unix(_, ref path) => {
+                fs::remove_file(path).ok();
+                fs::remove_file(lock_path(path)).ok();
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The `Stream` implementation.
+impl Stream {
+    /// Connects to the address.
+    ///
+    /// Gives up once the timeout elapses.
+    pub fn connect(address: &DebugAddress, timeout: Duration) -> io::Result<Stream> {
+        match *address {
+            DebugAddress::Tcp(ref address) => {
+                let mut result = Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                    "Unable to resolve the address"));
+                for address in address.as_str().to_socket_addrs()? {
+                    result = TcpStream::connect_timeout(&address, timeout);
+                    if result.is_ok() {
+                        break;
+                    }
+                }
+                Ok(Stream::Tcp(result?))
+            }
+            DebugAddress::Unix(ref path) => Stream::connect_unix(path),
+        }
+    }
+
+    #[cfg(unix)]
+    fn connect_unix(path: &PathBuf) -> io::Result<Stream> {
+        Ok(Stream::Unix(UnixStream::connect(path)?))
+    }
+
+    #[cfg(not(unix))]
+    fn connect_unix(_: &PathBuf) -> io::Result<Stream> {
+        Err(unsupported())
+    }
+
+    /// Creates a second handle to the same connection.
+    pub fn try_clone(&self) -> io::Result<Stream> {
+        match *self {
+            Stream::Tcp(ref stream) => Ok(Stream::Tcp(stream.try_clone()?)),
+            #[cfg(unix)]
+            Stream::Unix(ref stream) => Ok(Stream::Unix(stream.try_clone()?)),
+        }
+    }
+}
+
+/// Implements the `Read` trait for the `Stream` type.
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut stream) => stream.read(buf),
+        }
+    }
+}
+
+/// Implements the `Write` trait for the `Stream` type.
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref mut stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(ref mut stream) => stream.flush(),
+        }
+    }
+}
+
+/// Gets the path of the lock file guarding a Unix domain socket file.
+#[cfg(unix)]
+fn lock_path(path: &PathBuf) -> PathBuf {
+    let mut lock = path.clone().into_os_string();
+    lock.push(".lock");
+    PathBuf::from(lock)
+}
+
+/// Creates a lock file; fails if it already exists.
+#[cfg(unix)]
+fn create_lock(lock: &PathBuf) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(lock)
+}
+
+/// Gets the id of the process holding the lock; `None` if it is no longer running.
+#[cfg(unix)]
+fn lock_owner(lock: &PathBuf) -> Option<i32> {
+    let mut text = String::new();
+    File::open(lock).and_then(|mut file| file.read_to_string(&mut text)).ok()?;
+    let pid = text.trim().parse().ok().filter(|&pid| pid > 0)?;
+
+    // Signal 0 only tests if the process exists
+    // A process owned by another user cannot be signalled, but still exists.
+    let running = unsafe { libc::kill(pid, 0) } == 0 ||
+                  io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    match running {
+        true => Some(pid),
+        false => None,
+    }
+}
+
+/// Creates the error reported for Unix domain sockets on other platforms.
+#[cfg(not(unix))]
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Other,
+                   "Unix domain sockets are not supported on this platform")
+}
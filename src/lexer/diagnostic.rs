@@ -0,0 +1,123 @@
+use std::fmt::{Display, Formatter, Result};
+
+/// The `Severity` type.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Severity {
+    /// The source cannot be executed.
+    Error,
+
+    /// The source can be executed, but probably does not do what was intended.
+    Warning,
+}
+
+/// The `Span` type.
+///
+/// Uses the same line and position numbering as `Instruction`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Span {
+    /// The line.
+    pub line: u32,
+
+    /// The position relative to the line.
+    pub pos: u32,
+
+    /// The number of characters.
+    pub len: u32,
+}
+
+/// The `Diagnostic` type.
+///
+/// An error or warning reported by the lexer.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Diagnostic {
+    /// The severity.
+    pub severity: Severity,
+
+    /// The message.
+    pub message: String,
+
+    /// The offending part of the source.
+    pub span: Span,
+}
+
+/// The `Diagnostic` implementation.
+impl Diagnostic {
+    /// Constructs a new `Diagnostic`.
+    pub fn new(severity: Severity, message: String, span: Span) -> Diagnostic {
+        Diagnostic {
+            severity: severity,
+            message: message,
+            span: span,
+        }
+    }
+
+    /// Tests if the diagnostic is an error.
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders the diagnostic along with the offending line of the source.
+    ///
+    /// The offending characters are underlined with carets.
+    pub fn render(&self, source: &str) -> String {
+        let mut buf = format!("{}\n", self);
+
+        // Find the offending line
+        let line = match source.lines().nth(self.span.line as usize) {
+            Some(line) => line,
+            None => return buf,
+        };
+
+        // Underline the offending characters
+        buf.push_str(&format!("  |\n  | {}\n  | {}\n", line, self.span.underline(line)));
+        buf
+    }
+}
+
+/// The `Span` implementation.
+impl Span {
+    /// Underlines the characters of the span in the line with carets.
+    ///
+    /// At least one caret is shown, even if the span is empty or past the end of the line.
+    pub fn underline(&self, line: &str) -> String {
+
+        // Tabs are kept in the padding, so that the carets line up with the line.
+        let padding: String = line.chars()
+            .take(self.pos as usize)
+            .map(|chr| match chr {
+                '\t' => '\t',
+                _ => ' ',
+            })
+            .collect();
+        let available = line.chars().count().saturating_sub(self.pos as usize);
+        let carets = match (self.len as usize).min(available) {
+            0 => 1,
+            len => len,
+        };
+        format!("{}{}", padding, "^".repeat(carets))
+    }
+}
+
+/// Implements the `Display` trait for the `Severity` type.
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f,
+               "{}",
+               match *self {
+                   Severity::Error => "Error",
+                   Severity::Warning => "Warning",
+               })
+    }
+}
+
+/// Implements the `Display` trait for the `Diagnostic` type.
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f,
+               "{}: {} at ({}:{})",
+               self.severity,
+               self.message,
+               self.span.line,
+               self.span.pos)
+    }
+}
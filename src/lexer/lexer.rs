@@ -1,6 +1,16 @@
 use std::cell::Cell;
 use vm::opcodes::Opcode;
 use vm::instruction::Instruction;
+use lexer::diagnostic::{Diagnostic, Severity, Span};
+
+/// Characters which are commonly mistyped operators, along with the intended operator.
+const LOOKALIKES: [(char, char); 7] = [('{', '['),
+                                       ('}', ']'),
+                                       ('B', 'b'),
+                                       ('C', 'c'),
+                                       ('I', 'i'),
+                                       ('Q', 'q'),
+                                       ('X', 'x')];
 
 /// The `Lexer` type.
 pub struct Lexer {
@@ -20,6 +30,11 @@ pub struct Lexer {
     ///
     /// The collection is populated by the lexer while processing the source.
     pub tokens: Vec<Instruction>,
+
+    /// A collection of errors and warnings
+    ///
+    /// The collection is populated by the lexer while processing the source.
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 /// The `Lexer` implementation.
@@ -34,6 +49,7 @@ impl Lexer {
             current_pos: Cell::new(0),
             current_line: Cell::new(0),
             tokens: Vec::new(),
+            diagnostics: Vec::new(),
         }
     }
 
@@ -110,11 +126,37 @@ impl Lexer {
 
                 // Test if the character is part of a string literal
                 '"' => {
+                    let start = self.stream_pos.get();
                     self.skip(1);
                     let mut buf = String::with_capacity(32);
                     while self.can_advance(1) && self.peek(1) != '"' {
-                        buf.push(self.peek(1));
-                        self.skip(1);
+                        match self.peek(1) {
+
+                            // Decode escape sequences
+                            '\\' => {
+                                if let Some(chr) = self.read_escape() {
+                                    buf.push(chr);
+                                }
+                            }
+
+                            // Copy all other characters
+                            chr => {
+                                buf.push(chr);
+                                self.skip(1);
+                            }
+                        }
+                    }
+
+                    // Test if the string literal is terminated
+                    if !self.can_advance(1) {
+                        let span = Span {
+                            line: state.1,
+                            pos: state.0,
+                            len: (self.stream_pos.get() - start) as u32,
+                        };
+                        self.report(Severity::Error,
+                                    "Unterminated string literal".to_string(),
+                                    span);
                     }
                     self.skip(1);
                     self.create_instruction(state, buf, Opcode::Str);
@@ -128,12 +170,25 @@ impl Lexer {
                     self.skip(1);
                 }
 
-                // Ignore all other cases
-                _ => (),
+                // Warn about characters which look like mistyped operators
+                _ => {
+                    if let Some(&(_, op)) = LOOKALIKES.iter().find(|&&(x, _)| x == chr) {
+                        let span = self.span(1);
+                        self.report(Severity::Warning,
+                                    format!("Unknown character '{}', did you mean '{}'?", chr, op),
+                                    span);
+                    }
+                    self.skip(1);
+                }
             };
         }
     }
 
+    /// Tests if the lexer reported any errors.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|diagnostic| diagnostic.is_error())
+    }
+
     /// Tests if reading `n` more characters is possible.
     fn can_advance(&self, n: i32) -> bool {
         self.stream_pos.get() + n < self.stream.len() as i32
@@ -167,6 +222,81 @@ impl Lexer {
         }
     }
 
+    /// Reads an escape sequence, starting at the backslash.
+    ///
+    /// Supports `\0`, `\n`, `\r`, `\t`, `\\`, `\"`, `\xNN` and `\u{NNNNNN}`.
+    /// Reports an error and returns `None` if the escape sequence is invalid.
+    fn read_escape(&mut self) -> Option<char> {
+        let mut span = self.span(0);
+        let start = self.stream_pos.get();
+        self.skip(1);
+
+        // Leave unterminated string literals to the caller
+        if !self.can_advance(1) {
+            return None;
+        }
+        let chr = self.peek(1);
+        self.skip(1);
+
+        // Decode the escape sequence
+        let decoded = match chr {
+            '0' => Some('\0'),
+            'n' => Some('\n'),
+            'r' => Some('\r'),
+            't' => Some('\t'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            'x' => {
+                let digits = self.read_hex_digits(2);
+                match digits.len() {
+                    2 => u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32),
+                    _ => None,
+                }
+            }
+            'u' => {
+                match self.peek(1) {
+                    '{' => {
+                        self.skip(1);
+                        let digits = self.read_hex_digits(6);
+                        match (digits.len(), self.peek(1)) {
+                            (len, '}') if len > 0 => {
+                                self.skip(1);
+                                u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32)
+                            }
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        // Report invalid escape sequences
+        if decoded.is_none() {
+            let end = self.stream_pos.get();
+            let escape: String = self.stream[(start + 1) as usize..(end + 1) as usize]
+                .iter()
+                .cloned()
+                .collect();
+            span.len = (end - start) as u32;
+            self.report(Severity::Error,
+                        format!("Invalid escape sequence '{}'", escape),
+                        span);
+        }
+        decoded
+    }
+
+    /// Reads up to `max` hexadecimal digits.
+    fn read_hex_digits(&mut self, max: usize) -> String {
+        let mut digits = String::with_capacity(max);
+        while digits.len() < max && self.can_advance(1) && self.peek(1).is_digit(16) {
+            digits.push(self.peek(1));
+            self.skip(1);
+        }
+        digits
+    }
+
     /// Skips whitespace characters.
     fn skip_whitespace(&mut self) {
         while self.peek(1).is_whitespace() {
@@ -182,4 +312,18 @@ impl Lexer {
         let lex = Instruction::new(state.0, state.1, value, opcode, None);
         self.tokens.push(lex);
     }
+
+    /// Creates a span of `len` characters, starting at the next character.
+    fn span(&self, len: u32) -> Span {
+        Span {
+            line: self.current_line.get() as u32,
+            pos: self.current_pos.get() as u32,
+            len: len,
+        }
+    }
+
+    /// Adds a diagnostic to the collection.
+    fn report(&mut self, severity: Severity, message: String, span: Span) {
+        self.diagnostics.push(Diagnostic::new(severity, message, span));
+    }
 }
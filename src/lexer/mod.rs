@@ -1,2 +1,4 @@
 pub mod lexer;
+pub mod diagnostic;
 pub use lexer::lexer::Lexer;
+pub use lexer::diagnostic::{Diagnostic, Severity, Span};
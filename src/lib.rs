@@ -1,13 +1,26 @@
 extern crate rustc_serialize;
 extern crate byteorder;
 extern crate bincode;
+#[cfg(unix)]
+extern crate libc;
 
 mod lexer;
 mod optimizer;
 mod vm;
 mod debugger;
+mod bytecode;
 
-pub use lexer::Lexer;
-pub use optimizer::{Optimizer, OptimizerPass};
-pub use vm::VirtualMachine;
-pub use debugger::{DebugServer, DebugClient, DebugInformation};
+pub use lexer::{Lexer, Diagnostic, Severity, Span};
+pub use optimizer::{Optimizer, OptimizerPass, OptimizationLevel, PassStatistics, Divergence,
+                    Validation};
+pub use vm::{VirtualMachine, ExecutionStatus, PrintMode, Snapshot};
+pub use vm::{VmError, ErrorContext, SharedBuffer, ExecutionLimits, Limit};
+pub use vm::{CellConfig, CellWidth, Overflow, TapeEdge, Tape};
+pub use vm::instruction::Instruction;
+pub use vm::opcodes::Opcode;
+pub use bytecode::{Bytecode, BytecodeError};
+pub use debugger::{DebugServer, DebugClient, DebugInformation, DebugAddress};
+pub use debugger::{DebugCommand, DebugResponse, StopReason, DebugError};
+pub use debugger::{Breakpoint, BreakpointKind, Condition, Operand, Comparison};
+pub use debugger::{Registers, MemoryWindow, Source};
+pub use debugger::{PROTOCOL_VERSION, MAX_FRAME_LENGTH};
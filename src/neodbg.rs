@@ -1,42 +1,660 @@
+extern crate clap;
 extern crate neoqo;
-use neoqo::{DebugClient, DebugInformation};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use clap::{Arg, App};
+use neoqo::{DebugClient, DebugCommand, DebugResponse, DebugInformation, DebugError};
+use neoqo::{StopReason, Source, Breakpoint, BreakpointKind, Condition, DebugAddress};
+use neoqo::Span;
+
+/// The help text listing the available commands.
+const HELP: &'static str = "Commands:
+  c, continue      Resume the execution
+  s, step          Execute a single instruction
+  n, next          Execute the loop starting at the current instruction
+  u, until <line>  Resume the execution until the line is reached
+  pause            Stop the execution
+  b, break <line>[:<pos>] [if <condition>]
+                   Stop when the line or the position is reached,
+                   also when a loop jumps back to it
+  b, break tick <ticks> [if <condition>]
+                   Stop when the tick count is reached
+  w, watch cell[<index>] [if <condition>]
+                   Stop when the value of the cell changes
+  w, watch stack [if <condition>]
+                   Stop when the depth of the stack changes
+  d, delete <id>   Remove a breakpoint or a watchpoint
+  p, print cell    Show the value of the current cell
+  p, print cell[<index>]
+                   Show the value of a cell
+  stack            Show the stack
+  x, memory [<distance>]
+                   Show the cells around the cell pointer
+  set cell[<index>] <value>
+                   Set the value of a cell
+  set stack[<index>] <value>
+                   Set the value of a stack item, counted from the bottom
+  l, list [<line>] Show the source around the current instruction or the line
+  i, info [breakpoints|registers|stack|memory|source]
+                   Show information about the program
+  history          Show the command history
+  h, help          Show this help
+  q, quit          Detach from the program
+
+An empty line repeats the last command,
+!! repeats the last command and !<n> repeats the command <n> of the history.
+
+Conditions:
+  <operand> <comparison> <value>, where the operand is
+  cell, cell[<index>], top or depth, and the comparison is
+  one of ==, !=, <, <=, > and >=";
+
+/// The number of seconds to wait for the debug server by default.
+const DEFAULT_TIMEOUT: &'static str = "5";
+
+/// The number of cells shown on either side of the cell pointer by default.
+const DEFAULT_MEMORY_DISTANCE: u32 = 8;
+
+/// The number of source lines shown on either side of the listed line.
+const LIST_DISTANCE: u32 = 5;
+
+/// The name of the history file, which is stored in the home directory.
+const HISTORY_FILE: &'static str = ".neodbg_history";
+
+/// The maximum number of commands kept in the history.
+///
+/// The history file may hold up to twice as many commands,
+/// so that it only needs to be rewritten every `HISTORY_SIZE` commands.
+const HISTORY_SIZE: usize = 1000;
+
+/// The `Event` type.
+enum Event {
+    /// A line was read from the standard input; `None` at the end of the input.
+    Line(Option<String>),
+
+    /// A response was received from the server.
+    Response(Result<DebugResponse, DebugError>),
+}
+
+/// The `History` type.
+///
+/// The commands entered by the user, which are kept across sessions.
+struct History {
+    /// The commands; the last item is the most recent command.
+    entries: Vec<String>,
+
+    /// The history file; `None` if the home directory is unknown.
+    path: Option<PathBuf>,
+
+    /// The number of commands loaded from the history file.
+    loaded: usize,
+
+    /// The number of commands in the history file.
+    saved: usize,
+}
+
+/// The `Session` type.
+struct Session {
+    /// The connection used for sending commands.
+    client: DebugClient,
+
+    /// The source of the program; `None` if unknown.
+    source: Option<Source>,
+
+    /// The location at which the execution stopped most recently.
+    location: Option<DebugInformation>,
+
+    /// A value indicating whether the program is running.
+    running: bool,
+
+    /// The number of commands awaiting a response.
+    pending: usize,
+
+    /// The command history.
+    history: History,
+}
 
 // The main entry point of the application
 fn main() {
+
+    // Process command-line arguments
+    let matches = App::new("neodbg")
+        .version("0.0.1")
+        .author("Splitty <splittdev@gmail.com>")
+        .arg(Arg::with_name("connect")
+            .help("The address of the debug server; either host:port, a port \
+                   or the path of a Unix domain socket, e.g. unix:dbg.sock.")
+            .long("connect")
+            .takes_value(true))
+        .arg(Arg::with_name("timeout")
+            .help("The number of seconds to wait for the debug server.")
+            .long("timeout")
+            .takes_value(true)
+            .default_value(DEFAULT_TIMEOUT))
+        .get_matches();
+    let address = match matches.value_of("connect") {
+        Some(address) => DebugAddress::parse(address),
+        None => DebugAddress::default(),
+    };
+    let timeout = match matches.value_of("timeout").unwrap().parse() {
+        Ok(timeout) => Duration::from_secs(timeout),
+        Err(_) => fail("Invalid timeout."),
+    };
+
+    // Connect to the debug server
     let mut client = DebugClient::new();
-    print!("Connecting to debug server...");
-    match client.connect() {
-        true => println!("Connected!"),
-        false => {
-            println!("Failed!");
-            println!("Please start neoqo first:\n $ neoqo <input> --debug");
+    print!("Connecting to debug server on {}... ", address);
+    io::stdout().flush().ok();
+    match client.connect(&address, timeout) {
+        Ok(_) => println!("Connected!"),
+        Err(err) => {
+            println!("Failed! ({})", err);
+            println!("Please start neoqo first:\n $ neoqo <input> --debug-addr {}", address);
             return;
         }
     }
-    loop {
-        match client.get_update() {
-            None => continue,
-            Some(data) => {
-                if data.terminate {
-                    println!("Debugger session terminated by server.");
-                    return;
+
+    // Wait for the program to stop at the first instruction
+    let entry = match client.receive() {
+        Ok(DebugResponse::Stopped(data)) => data,
+        Ok(response) => fail(&format!("Unexpected response {:?}", response)),
+        Err(err) => fail(&format!("Debugger session terminated: {}", err)),
+    };
+
+    // Fetch the source
+    let source = match client.send(&DebugCommand::GetSource).and_then(|_| client.receive()) {
+        Ok(DebugResponse::Source(source)) => source,
+        Ok(response) => fail(&format!("Unexpected response {:?}", response)),
+        Err(err) => fail(&format!("Debugger session terminated: {}", err)),
+    };
+
+    // Receive the responses and read the commands on separate threads
+    // This allows pausing the program while it is running.
+    let (sender, events) = mpsc::channel();
+    let mut receiver = client.try_clone().expect("Unable to clone the connection.");
+    let responses = sender.clone();
+    thread::spawn(move || loop {
+        let response = receiver.receive();
+        let failed = response.is_err();
+        if responses.send(Event::Response(response)).is_err() || failed {
+            break;
+        }
+    });
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            match line {
+                Ok(line) => sender.send(Event::Line(Some(line))).ok(),
+                Err(_) => break,
+            };
+        }
+        sender.send(Event::Line(None)).ok();
+    });
+
+    // Run the debugger shell
+    let mut session = Session::new(client, source);
+    session.stopped(entry);
+    prompt();
+    for event in events {
+        match event {
+            Event::Line(Some(line)) => session.execute(&line),
+            Event::Line(None) => break,
+            Event::Response(Ok(response)) => session.receive(response),
+            Event::Response(Err(err)) => {
+                println!("Debugger session terminated: {}", err);
+                return;
+            }
+        }
+    }
+
+    // Detach from the program
+    // The program keeps running without the debugger.
+    println!();
+}
+
+/// The `History` implementation.
+impl History {
+    /// Loads the history from the history file.
+    ///
+    /// Stops at the first line, which cannot be read.
+    fn load() -> History {
+        let path = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        let entries: Vec<String> = match path.as_ref().and_then(|path| File::open(path).ok()) {
+            Some(f) => BufReader::new(f).lines().map_while(Result::ok).collect(),
+            None => Vec::new(),
+        };
+        let mut history = History {
+            loaded: entries.len(),
+            saved: entries.len(),
+            entries: entries,
+            path: path,
+        };
+        history.trim();
+        if history.saved >= 2 * HISTORY_SIZE {
+            history.save();
+        }
+        history
+    }
+
+    /// Resolves references to previous commands.
+    ///
+    /// An empty line refers to the last command of the current session,
+    /// `!!` refers to the last command and `!<n>` refers to the command `<n>`.
+    /// Returns `None` if the referenced command does not exist.
+    fn resolve(&self, line: &str) -> Option<String> {
+        match line {
+            "" if self.entries.len() == self.loaded => None,
+            "" | "!!" => self.entries.last().cloned(),
+            _ if line.starts_with('!') => {
+                let n: usize = line[1..].parse().ok()?;
+                match n {
+                    0 => None,
+                    n => self.entries.get(n - 1).cloned(),
                 }
-                handle(data);
             }
+            _ => Some(line.to_string()),
+        }
+    }
+
+    /// Adds a command to the history, unless it repeats the last command.
+    fn add(&mut self, line: &str) {
+        if self.entries.last().map_or(false, |last| last == line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        self.trim();
+
+        // Rewrite the history file once it holds twice as many commands as the history,
+        // so that the file does not grow any further
+        if self.saved + 1 >= 2 * HISTORY_SIZE {
+            self.save();
+            return;
+        }
+
+        // Append the command to the history file
+        // The history is not essential, which is why errors are ignored.
+        if let Some(ref path) = self.path {
+            if let Ok(mut f) = OpenOptions::new().create(true).append(true).open(path) {
+                writeln!(f, "{}", line).ok();
+            }
+        }
+        self.saved += 1;
+    }
+
+    /// Removes the oldest commands, which exceed the maximum size of the history.
+    fn trim(&mut self) {
+        if self.entries.len() > HISTORY_SIZE {
+            let excess = self.entries.len() - HISTORY_SIZE;
+            self.entries.drain(..excess);
+            self.loaded = self.loaded.saturating_sub(excess);
+        }
+    }
+
+    /// Writes the whole history to the history file, replacing its contents.
+    ///
+    /// The history is not essential, which is why errors are ignored.
+    fn save(&mut self) {
+        self.saved = self.entries.len();
+        if let Some(ref path) = self.path {
+            if let Ok(mut f) = File::create(path) {
+                for entry in &self.entries {
+                    writeln!(f, "{}", entry).ok();
+                }
+            }
+        }
+    }
+
+    /// Prints the history.
+    fn print(&self) {
+        for (i, entry) in self.entries.iter().enumerate() {
+            println!("{:>5}  {}", i + 1, entry);
         }
     }
 }
 
-fn handle(data: DebugInformation) {
-    match data.instr {
-        Some(instr) => {
-            println!("[Line {:03}:{:03}] Opcode={:?}; Arg={:?}; Optimized={}",
-                     instr.line,
-                     instr.pos,
-                     instr.opcode,
-                     instr.argument,
-                     instr.optimized);
+/// The `Session` implementation.
+impl Session {
+    /// Constructs a new `Session`.
+    fn new(client: DebugClient, source: Option<Source>) -> Session {
+        Session {
+            client: client,
+            source: source,
+            location: None,
+            running: false,
+            pending: 0,
+            history: History::load(),
         }
-        None => (),
     }
+
+    /// Executes a line entered by the user.
+    fn execute(&mut self, line: &str) {
+
+        // Resolve references to previous commands
+        let line = match self.history.resolve(line.trim()) {
+            Some(line) => line,
+            None => {
+                if !line.trim().is_empty() {
+                    println!("No such command in the history.");
+                }
+                self.prompt();
+                return;
+            }
+        };
+        self.history.add(&line);
+
+        // Execute the command
+        // Some commands are handled locally, the rest is sent to the server.
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("h"), None, None) | (Some("help"), None, None) => println!("{}", HELP),
+            (Some("q"), None, None) | (Some("quit"), None, None) => process::exit(0),
+            (Some("history"), None, None) => self.history.print(),
+            (Some("l"), line, None) | (Some("list"), line, None) => {
+                match line.map(|line| line.parse()) {
+                    Some(Ok(line)) => self.list(Some(line)),
+                    Some(Err(_)) => println!("Invalid line number."),
+                    None => self.list(None),
+                }
+            }
+            (Some("i"), Some("source"), None) | (Some("info"), Some("source"), None) => {
+                match self.source {
+                    Some(ref source) => {
+                        println!("{} ({} lines)", source.name, source.text.lines().count())
+                    }
+                    None => println!("No source available."),
+                }
+            }
+            _ => {
+                match parse(&line) {
+                    Some(commands) => {
+                        for command in commands {
+                            self.send(command);
+                        }
+                    }
+                    None => println!("Invalid command; type 'help' for a list of commands."),
+                }
+            }
+        }
+        self.prompt();
+    }
+
+    /// Sends a command to the server.
+    fn send(&mut self, command: DebugCommand) {
+        if let Err(err) = self.client.send(&command) {
+            fail(&format!("Unable to send command: {}", err));
+        }
+        self.pending += 1;
+        match command {
+            DebugCommand::Continue | DebugCommand::RunToLine(_) => println!("Continuing."),
+            _ => (),
+        }
+    }
+
+    /// Shows a response received from the server.
+    fn receive(&mut self, response: DebugResponse) {
+        self.pending = self.pending.saturating_sub(1);
+        match response {
+            DebugResponse::Running => self.running = true,
+            DebugResponse::Stopped(data) => {
+                self.running = false;
+                self.stopped(data);
+            }
+            DebugResponse::BreakpointSet(id) => println!("Breakpoint {} set.", id),
+            DebugResponse::BreakpointRemoved(id) => println!("Breakpoint {} removed.", id),
+            DebugResponse::Breakpoints(breakpoints) => {
+                match breakpoints.len() {
+                    0 => println!("No breakpoints."),
+                    _ => {
+                        for (id, breakpoint) in breakpoints {
+                            println!("{:>3}: {}", id, breakpoint);
+                        }
+                    }
+                }
+            }
+            DebugResponse::Registers(registers) => {
+                println!("IP={}; CP={}; Ticks={}; PrintMode={:?}; StackDepth={}",
+                         registers.ip,
+                         registers.cp,
+                         registers.ticks,
+                         registers.print_mode,
+                         registers.stack_depth);
+            }
+            DebugResponse::Memory(window) => {
+                match window.cells.len() {
+                    1 => println!("cell[{}] = {}", window.start, window.cells[0]),
+                    _ => {
+                        for (i, value) in window.cells.iter().enumerate() {
+                            let index = window.start + i as i64;
+                            println!("{} [{:>5}] {}",
+                                     match index == window.cp {
+                                         true => "->",
+                                         false => "  ",
+                                     },
+                                     index,
+                                     value);
+                        }
+                    }
+                }
+            }
+            DebugResponse::Stack(stack) => {
+                match stack.len() {
+                    0 => println!("The stack is empty."),
+                    _ => {
+                        for (index, value) in stack.iter().enumerate().rev() {
+                            println!("[{:>5}] {}", index, value);
+                        }
+                    }
+                }
+            }
+            DebugResponse::Modified => (),
+            DebugResponse::Source(source) => self.source = source,
+            DebugResponse::Error(err) => println!("Error: {}", err),
+        }
+        self.prompt();
+    }
+
+    /// Shows the location at which the execution stopped.
+    ///
+    /// Exits once the program has terminated.
+    fn stopped(&mut self, data: DebugInformation) {
+        let reason = match data.reason {
+            StopReason::Entry => "Stopped at the first instruction".to_string(),
+            StopReason::Pause => "Paused".to_string(),
+            StopReason::Step => "Stepped".to_string(),
+            StopReason::Line(line) => format!("Reached line {}", line),
+            StopReason::Breakpoint(id) => format!("Breakpoint {}", id),
+            StopReason::Watchpoint(id, previous, current) => {
+                format!("Watchpoint {}: {} -> {}", id, previous, current)
+            }
+            StopReason::Halted(code) => format!("Program halted with exit code {}", code),
+            StopReason::Error(ref err) => format!("Program aborted: {}", err),
+        };
+        match data.instr {
+            Some(ref instr) => {
+                println!("{} at {}:{}; Opcode={:?}; Arg={:?}; Optimized={}",
+                         reason,
+                         instr.line,
+                         instr.pos,
+                         instr.opcode,
+                         instr.argument,
+                         instr.optimized);
+                if let Some(ref source) = self.source {
+                    show_lines(source, instr.line, instr.line, Some((instr.line, instr.pos)));
+                }
+            }
+            None => println!("{}.", reason),
+        }
+        if data.is_terminated() {
+            println!("Debugger session terminated by server.");
+            process::exit(0);
+        }
+        self.location = Some(data);
+    }
+
+    /// Shows the source around the line, or around the current instruction.
+    fn list(&self, line: Option<u32>) {
+        let source = match self.source {
+            Some(ref source) => source,
+            None => {
+                println!("No source available.");
+                return;
+            }
+        };
+        let current = self.location
+            .as_ref()
+            .and_then(|location| location.instr.as_ref())
+            .map(|instr| (instr.line, instr.pos));
+        let center = line.or(current.map(|(line, _)| line)).unwrap_or(0);
+        show_lines(source,
+                   center.saturating_sub(LIST_DISTANCE),
+                   center.saturating_add(LIST_DISTANCE),
+                   current);
+    }
+
+    /// Prompts for the next command, unless the program is running
+    /// or responses are outstanding.
+    fn prompt(&self) {
+        if !self.running && self.pending == 0 {
+            prompt();
+        }
+    }
+}
+
+fn parse(line: &str) -> Option<Vec<DebugCommand>> {
+
+    // Split off the condition
+    let (line, condition) = match line.find(" if ") {
+        Some(index) => (&line[..index], Some(Condition::parse(&line[index + 4..])?)),
+        None => (line, None),
+    };
+    let mut words = line.split_whitespace();
+    let commands = match words.next() {
+        Some("b") | Some("break") => {
+            let kind = match words.next()? {
+                "tick" => BreakpointKind::Ticks(words.next()?.parse().ok()?),
+                position => parse_position(position)?,
+            };
+            vec![DebugCommand::SetBreakpoint(Breakpoint {
+                     kind: kind,
+                     condition: condition,
+                 })]
+        }
+        Some("w") | Some("watch") => {
+            let kind = match words.next()? {
+                "stack" => BreakpointKind::WatchStack,
+                cell => BreakpointKind::WatchCell(parse_index(cell, "cell")?),
+            };
+            vec![DebugCommand::SetBreakpoint(Breakpoint {
+                     kind: kind,
+                     condition: condition,
+                 })]
+        }
+        _ if condition.is_some() => return None,
+        Some("c") | Some("continue") => vec![DebugCommand::Continue],
+        Some("s") | Some("step") => vec![DebugCommand::Step],
+        Some("n") | Some("next") => vec![DebugCommand::StepOverLoop],
+        Some("u") | Some("until") => vec![DebugCommand::RunToLine(words.next()?.parse().ok()?)],
+        Some("pause") => vec![DebugCommand::Pause],
+        Some("d") | Some("delete") => {
+            vec![DebugCommand::RemoveBreakpoint(words.next()?.parse().ok()?)]
+        }
+        Some("p") | Some("print") => {
+            match words.next()? {
+                "cell" => vec![DebugCommand::GetMemory(0)],
+                "stack" => vec![DebugCommand::GetStack],
+                cell => vec![DebugCommand::GetCell(parse_index(cell, "cell")?)],
+            }
+        }
+        Some("stack") => vec![DebugCommand::GetStack],
+        Some("x") | Some("memory") => vec![parse_memory(words.next())?],
+        Some("set") => {
+            let target = words.next()?;
+            let value = words.next()?.parse().ok()?;
+            match parse_index(target, "cell") {
+                Some(index) => vec![DebugCommand::SetCell(index, value)],
+                None => vec![DebugCommand::SetStackItem(parse_index(target, "stack")?, value)],
+            }
+        }
+        Some("i") | Some("info") => {
+            match words.next() {
+                Some("breakpoints") => vec![DebugCommand::ListBreakpoints],
+                Some("registers") => vec![DebugCommand::GetRegisters],
+                Some("stack") => vec![DebugCommand::GetStack],
+                Some("memory") => vec![parse_memory(words.next())?],
+                Some(_) => return None,
+                None => vec![DebugCommand::GetRegisters, DebugCommand::ListBreakpoints],
+            }
+        }
+        _ => return None,
+    };
+    match words.next() {
+        Some(_) => None,
+        None => Some(commands),
+    }
+}
+
+fn parse_position(text: &str) -> Option<BreakpointKind> {
+    let mut parts = text.splitn(2, ':');
+    let line = parts.next()?.parse().ok()?;
+    let pos = match parts.next() {
+        Some(pos) => Some(pos.parse().ok()?),
+        None => None,
+    };
+    Some(BreakpointKind::Line(line, pos))
+}
+
+fn parse_index<T: FromStr>(text: &str, name: &str) -> Option<T> {
+    if text.starts_with(name) && text[name.len()..].starts_with('[') && text.ends_with(']') {
+        text[name.len() + 1..text.len() - 1].parse().ok()
+    } else {
+        None
+    }
+}
+
+fn parse_memory(distance: Option<&str>) -> Option<DebugCommand> {
+    match distance {
+        Some(distance) => Some(DebugCommand::GetMemory(distance.parse().ok()?)),
+        None => Some(DebugCommand::GetMemory(DEFAULT_MEMORY_DISTANCE)),
+    }
+}
+
+/// Shows the lines `first..=last` of the source.
+///
+/// The current line is marked, and the current position is underlined with a caret.
+fn show_lines(source: &Source, first: u32, last: u32, current: Option<(u32, u32)>) {
+    for (i, text) in source.text.lines().enumerate() {
+        let line = i as u32;
+        if line < first || line > last {
+            continue;
+        }
+        match current {
+            Some((current_line, pos)) if current_line == line => {
+                let span = Span {
+                    line: line,
+                    pos: pos,
+                    len: 1,
+                };
+                println!("=> {:>4} | {}", line, text);
+                println!("        | {}", span.underline(text));
+            }
+            _ => println!("   {:>4} | {}", line, text),
+        }
+    }
+}
+
+fn prompt() {
+    print!("(neodbg) ");
+    io::stdout().flush().ok();
+}
+
+fn fail(message: &str) -> ! {
+    println!("{}", message);
+    process::exit(1);
 }
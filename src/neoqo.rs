@@ -1,9 +1,16 @@
 extern crate clap;
 extern crate neoqo;
 use std::fs::File;
-use std::io::Read;
-use clap::{Arg, App};
-use neoqo::{Lexer, Optimizer, OptimizerPass, VirtualMachine};
+use std::io::{self, Cursor, Read, Write};
+use std::path::Path;
+use std::process;
+use clap::{Arg, ArgMatches, App, AppSettings, SubCommand};
+use neoqo::{Lexer, Optimizer, OptimizerPass, OptimizationLevel, VirtualMachine, Bytecode};
+use neoqo::{CellConfig, CellWidth, Overflow, TapeEdge, Tape, ExecutionLimits};
+use neoqo::{Source, DebugServer, DebugAddress, Validation};
+
+/// The tick limit of the programs ran by `--validate`.
+const VALIDATION_TICKS: u64 = 100_000_000;
 
 /// The main entry point of the application.
 fn main() {
@@ -12,6 +19,7 @@ fn main() {
     let matches = App::new("neoqo")
         .version("0.0.1")
         .author("Splitty <splittdev@gmail.com>")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(Arg::with_name("input")
             .help("The input file.")
             .required(true)
@@ -23,58 +31,357 @@ fn main() {
         .arg(Arg::with_name("debug")
             .long("debug")
             .takes_value(false))
+        .arg(Arg::with_name("debug-addr")
+            .help("The address the debugger connects to; either host:port, a port \
+                   or the path of a Unix domain socket, e.g. unix:dbg.sock. Implies --debug.")
+            .long("debug-addr")
+            .takes_value(true))
+        .args(&cell_args())
+        .arg(Arg::with_name("sparse")
+            .help("Only stores non-zero cells.")
+            .long("sparse")
+            .takes_value(false))
+        .args(&optimization_args())
+        .arg(Arg::with_name("validate")
+            .help("Runs the program before and after every optimization pass on the same \
+                   input and reports the first pass, which changes its behaviour. \
+                   Only applies to source files.")
+            .long("validate")
+            .takes_value(false))
+        .subcommand(SubCommand::with_name("compile")
+            .about("Compiles a qo program to bytecode.")
+            .arg(Arg::with_name("input")
+                .help("The input file.")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("output")
+                .help("The output file. Defaults to the input file with a .qoc extension.")
+                .short("o")
+                .long("output")
+                .takes_value(true))
+            .arg(Arg::with_name("strip")
+                .help("Omits the debug information.")
+                .long("strip")
+                .takes_value(false))
+            .args(&cell_args())
+            .args(&optimization_args()))
         .get_matches();
 
-    // Retrieve the input code from the specified source
-    let mut source = String::new();
-    match matches.value_of("input") {
+    // Compile the program, if requested
+    if let Some(matches) = matches.subcommand_matches("compile") {
+        compile(matches.value_of("input").unwrap(),
+                matches.value_of("output"),
+                !matches.is_present("strip"),
+                matches);
+        return;
+    }
+
+    // Read the whole input up front, if the optimization is validated
+    // That way, the validation and the program itself receive the same input.
+    let input = match matches.is_present("validate") {
+        true => {
+            let mut input = Vec::new();
+            io::stdin().read_to_end(&mut input).expect("Unable to read the standard input");
+            Some(input)
+        }
+        false => None,
+    };
 
-        // A filename was specified
-        Some(filename) => {
-            // Open the file
-            let mut f = File::open(filename)
-                .expect(&format!("Unable to open specified file: {}", filename));
+    // Load the instructions from the specified file
+    // Bytecode files can be executed directly, source files are lexed and optimized first.
+    let filename = matches.value_of("input").unwrap();
+    let data = read_file(filename);
+    // The cell options default to the configuration the bytecode was compiled for.
+    let (mut vm, cell_config) = match Bytecode::is_bytecode(&data) {
+        true => {
+            let bytecode = match Bytecode::read(&mut Cursor::new(data)) {
+                Ok(bytecode) => bytecode,
+                Err(err) => fail(&format!("Unable to load {}: {}", filename, err)),
+            };
+            if input.is_some() {
+                fail(&format!("Unable to validate {}: it is optimized already, \
+                               pass --validate when running the source instead",
+                              filename));
+            }
+            let cell_config = cell_config(&matches, bytecode.cell_config);
+            if !bytecode.supports(&cell_config) {
+                fail(&format!("Unable to run {}: it was optimized for another cell \
+                               configuration, compile it again using the same options",
+                              filename));
+            }
+            let mut vm = VirtualMachine::new(bytecode.to_instructions(), None, None);
+            vm.set_jump_table(bytecode.jump_table);
+            (vm, cell_config)
+        }
+        false => {
+            let source = decode_source(filename, data);
+            let input = input.as_ref().map(Vec::as_slice);
+            let optimizer = optimize(source.clone(), &matches, input);
+            let mut vm = VirtualMachine::new(optimizer.instructions, Option::None, Option::None);
+            vm.set_source(Source {
+                name: filename.to_string(),
+                text: source,
+            });
+            (vm, cell_config(&matches, CellConfig::new()))
+        }
+    };
+
+    // Interpret the instructions
+    configure(&mut vm, &matches, cell_config);
+    if let Some(input) = input {
+        vm.set_input(Box::new(Cursor::new(input)));
+    }
+    let result = match matches.is_present("debug") || matches.is_present("debug-addr") {
+        true => {
+            let address = match matches.value_of("debug-addr") {
+                Some(address) => DebugAddress::parse(address),
+                None => DebugAddress::default(),
+            };
+            match DebugServer::bind(&address) {
+                Ok(server) => vm.run_with_debugger(server),
+                Err(err) => fail(&format!("Error: {}", err)),
+            }
+        }
+        false => vm.run(),
+    };
 
-            // Read the file
-            f.read_to_string(&mut source)
-                .expect(&format!("Unable to read the specified file: {}", filename));
+    // Exit with the exit code of the program or report runtime errors
+    io::stdout().flush().ok();
+    match result {
+        Ok(code) => process::exit(code),
+        Err(err) => {
+            writeln!(&mut io::stderr(), "\nError: {}", err).ok();
+            process::exit(1);
         }
+    }
+}
+
+/// Applies the cell configuration and the memory selected on the command line.
+fn configure(vm: &mut VirtualMachine, matches: &ArgMatches, cell_config: CellConfig) {
+    vm.set_cell_config(cell_config);
+    if matches.is_present("sparse") {
+        vm.set_tape(Tape::sparse(128));
+    }
+}
+
+/// Gets the cell configuration selected on the command line.
+///
+/// Options, which are not given, are taken from the default configuration.
+fn cell_config(matches: &ArgMatches, default: CellConfig) -> CellConfig {
+    let mut cell_config = default;
+    match matches.value_of("cell-width") {
+        Some("u8") => cell_config.width = CellWidth::U8,
+        Some("u16") => cell_config.width = CellWidth::U16,
+        Some("u32") => cell_config.width = CellWidth::U32,
+        Some("i64") => cell_config.width = CellWidth::I64,
+        _ => (),
+    }
+    match matches.value_of("overflow") {
+        Some("wrap") => cell_config.overflow = Overflow::Wrap,
+        Some("saturate") => cell_config.overflow = Overflow::Saturate,
+        Some("error") => cell_config.overflow = Overflow::Error,
+        _ => (),
+    }
+    match matches.value_of("tape-edge") {
+        Some("error") => cell_config.edge = TapeEdge::Error,
+        Some("clamp") => cell_config.edge = TapeEdge::Clamp,
+        Some("wrap") => cell_config.edge = TapeEdge::Wrap,
+        Some("grow") => cell_config.edge = TapeEdge::Grow,
+        _ => (),
+    }
+    cell_config
+}
+
+/// Compiles a source file to bytecode.
+fn compile(filename: &str, output: Option<&str>, debug_info: bool, matches: &ArgMatches) {
+
+    // Lex and optimize the source
+    let data = read_file(filename);
+    let optimizer = optimize(decode_source(filename, data), matches, None);
+
+    // Create the bytecode
+    let bytecode = match Bytecode::new(optimizer.instructions.clone(),
+                                       optimizer.passes().to_vec(),
+                                       cell_config(matches, CellConfig::new()),
+                                       debug_info) {
+        Ok(bytecode) => bytecode,
+        Err(err) => fail(&format!("Error: {}", err)),
+    };
 
-        // No filename was specified
-        _ => {}
+    // Write the bytecode
+    let output = match output {
+        Some(output) => output.to_string(),
+        None => Path::new(filename).with_extension("qoc").to_string_lossy().into_owned(),
+    };
+    let mut f = File::create(&output)
+        .expect(&format!("Unable to create the specified file: {}", output));
+    if let Err(err) = bytecode.write(&mut f) {
+        fail(&format!("Unable to write {}: {}", output, err));
     }
+}
+
+/// Reads the contents of a file.
+fn read_file(filename: &str) -> Vec<u8> {
+
+    // Open the file
+    let mut f = File::open(filename)
+        .expect(&format!("Unable to open specified file: {}", filename));
+
+    // Read the file
+    let mut data = Vec::new();
+    f.read_to_end(&mut data)
+        .expect(&format!("Unable to read the specified file: {}", filename));
+    data
+}
+
+/// Decodes the contents of a source file.
+fn decode_source(filename: &str, data: Vec<u8>) -> String {
+    String::from_utf8(data).expect(&format!("Unable to read the specified file: {}", filename))
+}
+
+/// Gets the command-line arguments which control the cell configuration.
+///
+/// The optimizer only runs the passes, which preserve the behaviour of programs
+/// using the cell configuration.
+fn cell_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![Arg::with_name("cell-width")
+             .help("The width of a cell.")
+             .long("cell-width")
+             .takes_value(true)
+             .possible_values(&["u8", "u16", "u32", "i64"]),
+         Arg::with_name("overflow")
+             .help("What happens if a cell value overflows.")
+             .long("overflow")
+             .takes_value(true)
+             .possible_values(&["wrap", "saturate", "error"]),
+         Arg::with_name("tape-edge")
+             .help("What happens if the cell pointer leaves the memory.")
+             .long("tape-edge")
+             .takes_value(true)
+             .possible_values(&["error", "clamp", "wrap", "grow"])]
+}
+
+/// Gets the command-line arguments which control the optimization.
+fn optimization_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![Arg::with_name("opt-level")
+             .help("The optimization level. Defaults to 2.")
+             .short("O")
+             .takes_value(true)
+             .possible_values(&["0", "1", "2", "3"]),
+         Arg::with_name("pass")
+             .help("Enables an optimization pass.")
+             .long("pass")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .possible_values(&pass_names()),
+         Arg::with_name("no-pass")
+             .help("Disables an optimization pass.")
+             .long("no-pass")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .possible_values(&pass_names()),
+         Arg::with_name("opt-stats")
+             .help("Prints statistics about the optimization passes.")
+             .long("opt-stats")
+             .takes_value(false)]
+}
+
+/// Gets the short names of all optimization passes.
+fn pass_names() -> Vec<&'static str> {
+    OptimizerPass::all().iter().map(|pass| pass.name()).collect()
+}
+
+/// Gets the optimization passes selected on the command line.
+///
+/// Starts with the passes of the optimization level,
+/// then adds the passes enabled by `--pass` and removes the ones disabled by `--no-pass`.
+fn selected_passes(matches: &ArgMatches) -> Vec<OptimizerPass> {
+    let level = matches.value_of("opt-level")
+        .and_then(|level| level.parse().ok())
+        .and_then(OptimizationLevel::from_number)
+        .unwrap_or_default();
+    let named = |name: &str| {
+        matches.values_of(name)
+            .map(|names| names.filter_map(OptimizerPass::from_name).collect())
+            .unwrap_or(Vec::new())
+    };
+    let (enabled, disabled): (Vec<_>, Vec<_>) = (named("pass"), named("no-pass"));
+
+    // Keep the order of `OptimizerPass::all`
+    OptimizerPass::all()
+        .iter()
+        .cloned()
+        .filter(|pass| level.includes(*pass) || enabled.contains(pass))
+        .filter(|pass| !disabled.contains(pass))
+        .collect()
+}
+
+/// Tokenizes and optimizes the source.
+///
+/// Validates the optimization passes against the input, if specified.
+/// Prints statistics about the optimization passes, if requested.
+fn optimize(source: String, matches: &ArgMatches, input: Option<&[u8]>) -> Optimizer {
 
     // Tokenize the source
-    let mut lexer = Lexer::new(source);
+    let mut lexer = Lexer::new(source.clone());
     lexer.tokenize();
 
-    // Run basic optimization passes
-    //
-    // A quick overview of why I choose this specific order
-    // of optimization passes:
-    //
-    // 1) OptimizeIncDecPtrChains
-    //    This pass cleans up pointer movement operations.
-    //    By having this execute before the OptimizeIncDecValChains pass,
-    //    the chance of eliminating effectively useless loops is higher,
-    //    which makes the OptimizeIncDecValChains pass work better.
-    // 2) OptimizeIncDecValChains
-    //    This pass collapses multiple inc/dec cell value instructions
-    //    into just one, which frees the way for the OptimizeClearLoops pass.
-    // 3) OptimizeClearLoops
-    //    This pass turns clear loops into a single clear instruction.
-    //    It depends on a specific sequence of operations, which is why
-    //    the OptimizeIncDecValChains pass should always run before this one.
-    let mut optimizer = Optimizer::new(lexer.tokens.clone(), None);
-    optimizer.add_pass(OptimizerPass::OptimizeIncDecPtrChains);
-    optimizer.add_pass(OptimizerPass::OptimizeIncDecValChains);
-    optimizer.add_pass(OptimizerPass::OptimizeClearLoops);
-    optimizer.optimize(2);
+    // Report errors and warnings
+    // The source is not executed if it contains any errors.
+    for diagnostic in &lexer.diagnostics {
+        write!(&mut io::stderr(), "{}", diagnostic.render(&source)).ok();
+    }
+    if lexer.has_errors() {
+        process::exit(1);
+    }
 
-    // Interpret the instructions
-    let mut vm = VirtualMachine::new(optimizer.instructions, Option::None, Option::None);
-    match matches.occurrences_of("debug") {
-        0 => vm.run(),
-        _ => vm.run_with_debugger(),
+    // Run the selected optimization passes
+    // See `OptimizerPass::all` for the order of the passes.
+    let mut optimizer = Optimizer::new(lexer.tokens.clone(), Some(selected_passes(matches)));
+    let cell_config = cell_config(matches, CellConfig::new());
+    optimizer.set_cell_config(cell_config);
+    match input {
+        Some(input) => {
+            let result = optimizer.optimize_validated(input, |vm| {
+                configure(vm, matches, cell_config);
+                vm.set_limits(ExecutionLimits {
+                    max_ticks: Some(VALIDATION_TICKS),
+                    ..ExecutionLimits::new()
+                });
+            });
+            match result {
+                Ok(Validation::Validated) => (),
+                Ok(Validation::Skipped(limit)) => {
+                    writeln!(&mut io::stderr(),
+                             "Warning: Validation skipped ({} limit exceeded before optimizing)",
+                             limit)
+                        .ok();
+                }
+                Err(divergence) => fail(&format!("Validation failed: {}", divergence)),
+            }
+        }
+        None => optimizer.optimize(),
+    }
+
+    // Print the statistics
+    if matches.is_present("opt-stats") {
+        let mut stderr = io::stderr();
+        writeln!(&mut stderr,
+                 "Optimized to {} instructions in {} iterations",
+                 optimizer.instructions.len(),
+                 optimizer.iterations())
+            .ok();
+        for statistics in optimizer.statistics() {
+            writeln!(&mut stderr, "  {}", statistics).ok();
+        }
     }
+    optimizer
+}
+
+/// Prints an error message and exits.
+fn fail(message: &str) -> ! {
+    writeln!(&mut io::stderr(), "{}", message).ok();
+    process::exit(1);
 }
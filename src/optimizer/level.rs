@@ -0,0 +1,66 @@
+use optimizer::OptimizerPass;
+
+/// The `OptimizationLevel` type.
+///
+/// A named preset of optimization passes.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OptimizationLevel {
+    /// No optimization (`-O0`).
+    None,
+
+    /// The basic chain and clear-loop passes (`-O1`).
+    Basic,
+
+    /// All passes, which preserve the behaviour of every program (`-O2`).
+    Default,
+
+    /// All passes, including the ones which may hide errors (`-O3`).
+    Aggressive,
+}
+
+/// The `OptimizationLevel` implementation.
+impl OptimizationLevel {
+    /// Gets the optimization level for the number used by `-O<n>`.
+    pub fn from_number(number: u8) -> Option<OptimizationLevel> {
+        match number {
+            0 => Some(OptimizationLevel::None),
+            1 => Some(OptimizationLevel::Basic),
+            2 => Some(OptimizationLevel::Default),
+            3 => Some(OptimizationLevel::Aggressive),
+            _ => None,
+        }
+    }
+
+    /// Gets the optimization passes, in the order they should run.
+    pub fn passes(&self) -> Vec<OptimizerPass> {
+        OptimizerPass::all()
+            .iter()
+            .cloned()
+            .filter(|&pass| self.includes(pass))
+            .collect()
+    }
+
+    /// Tests if the optimization level includes the pass.
+    pub fn includes(&self, pass: OptimizerPass) -> bool {
+        match *self {
+            OptimizationLevel::None => false,
+            OptimizationLevel::Basic => {
+                match pass {
+                    OptimizerPass::OptimizeIncDecPtrChains |
+                    OptimizerPass::OptimizeIncDecValChains |
+                    OptimizerPass::OptimizeClearLoops => true,
+                    _ => false,
+                }
+            }
+            OptimizationLevel::Default => pass != OptimizerPass::OptimizeStackOperations,
+            OptimizationLevel::Aggressive => true,
+        }
+    }
+}
+
+/// Implements the `Default` trait for the `OptimizationLevel` type.
+impl Default for OptimizationLevel {
+    fn default() -> OptimizationLevel {
+        OptimizationLevel::Default
+    }
+}
@@ -1,3 +1,9 @@
 pub mod optimizer;
+pub mod statistics;
+pub mod level;
+pub mod validation;
 pub use optimizer::optimizer::{Optimizer, OptimizerPass};
+pub use optimizer::statistics::PassStatistics;
+pub use optimizer::level::OptimizationLevel;
+pub use optimizer::validation::{Divergence, Validation};
 pub const OPTIMIZED_VALUE: &'static str = "__optimizer_generated";
@@ -1,9 +1,18 @@
+use std::collections::BTreeMap;
+use std::mem;
+use std::time::Instant;
 use vm::instruction::Instruction;
 use vm::opcodes::Opcode;
 use optimizer::OPTIMIZED_VALUE;
+use optimizer::statistics::PassStatistics;
+use optimizer::validation::{Behaviour, Divergence, Validation};
+use vm::{VirtualMachine, CellConfig, Overflow, TapeEdge};
+
+/// The default maximum number of iterations. See `Optimizer::set_max_iterations`.
+const DEFAULT_MAX_ITERATIONS: usize = 16;
 
 /// The `OptimizerPass` type.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, RustcEncodable, RustcDecodable, PartialEq, Eq, Debug)]
 pub enum OptimizerPass {
     /// Clear-loop optimization pass.
     ///
@@ -21,6 +30,163 @@ pub enum OptimizerPass {
     /// Create a single `Opcode::IncPtr` or `Opcode::DecPtr` from a
     /// chain of cell pointer increment and decrement operators.
     OptimizeIncDecPtrChains,
+
+    /// Multiplication loop optimization pass.
+    ///
+    /// Creates a sequence of `Opcode::MulAdd` and `Opcode::MulSub`
+    /// instructions, followed by a single `Opcode::Clear`, from
+    /// `[->++>+++<<]`-style loops, which decrement the start cell by one
+    /// and return to it at the end of every iteration.
+    ///
+    /// Only runs for unsigned cells on a growing or bounded tape. A loop starting
+    /// at a negative cell never ends, and clamped or wrapped pointer movement
+    /// does not add up.
+    OptimizeMultiplyLoops,
+
+    /// Scan loop optimization pass.
+    ///
+    /// Creates a single `Opcode::ScanLeft` or `Opcode::ScanRight`
+    /// from `[<]`-style loops, which search for a zero cell.
+    OptimizeScanLoops,
+
+    /// Offset addressing optimization pass.
+    ///
+    /// Removes the cell pointer movement between instructions by
+    /// addressing their cells relative to the cell pointer instead.
+    /// See `Instruction.offset` for details.
+    ///
+    /// The net pointer movement is deferred to the next instruction,
+    /// which cannot address cells using an offset, e.g. a loop boundary.
+    ///
+    /// Only runs on a growing tape. Clamped or wrapped pointer movement does not add up,
+    /// and a bounded tape would miss the pointer leaving the memory between two accesses.
+    OptimizeOffsets,
+
+    /// Stack operation optimization pass.
+    ///
+    /// Removes `:;` pairs and `\\` double swaps,
+    /// and creates a single `Opcode::Peek` from `&;`.
+    ///
+    /// Removing a double swap also removes the stack underflow error
+    /// it would report if the stack held less than two values.
+    OptimizeStackOperations,
+
+    /// Print-string optimization pass.
+    ///
+    /// Creates a single `Opcode::PrintStr` from `"..."(;.);`,
+    /// which pushes a string and prints it character by character.
+    OptimizePrintStrings,
+
+    /// Dead code elimination pass.
+    ///
+    /// Removes loops which can never be entered, e.g. `[` right after `]`
+    /// of a loop without `!`, and the instructions which can never be reached
+    /// after `q` and `!`.
+    OptimizeDeadCode,
+
+    /// Constant propagation pass.
+    ///
+    /// Creates a single `Opcode::Set` from a sequence of `Opcode::Inc`,
+    /// `Opcode::Dec`, `Opcode::Double` and `Opcode::Halve` instructions,
+    /// which start at a cell with a known value, e.g. `[-]++**++`.
+    ///
+    /// Only folds values between 0 and 255, which every cell configuration
+    /// handles the same way.
+    OptimizeConstants,
+}
+
+/// The `OptimizerPass` implementation.
+impl OptimizerPass {
+    /// Gets all optimization passes, in the order they should run.
+    ///
+    /// A quick overview of why I choose this specific order
+    /// of optimization passes:
+    ///
+    /// 1) `OptimizeIncDecPtrChains`
+    ///    This pass cleans up pointer movement operations.
+    ///    By having this execute before the `OptimizeIncDecValChains` pass,
+    ///    the chance of eliminating effectively useless loops is higher,
+    ///    which makes the `OptimizeIncDecValChains` pass work better.
+    /// 2) `OptimizeIncDecValChains`
+    ///    This pass collapses multiple inc/dec cell value instructions
+    ///    into just one, which frees the way for the `OptimizeClearLoops` pass.
+    /// 3) `OptimizeClearLoops`
+    ///    This pass turns clear loops into a single clear instruction.
+    ///    It depends on a specific sequence of operations, which is why
+    ///    the `OptimizeIncDecValChains` pass should always run before this one.
+    /// 4) `OptimizeMultiplyLoops`
+    ///    This pass turns the remaining pointer-neutral loops, which move
+    ///    the value of a cell into other cells, into multiplication instructions.
+    ///    It runs after the `OptimizeClearLoops` pass, so that the cheaper
+    ///    clear instruction is used for clear loops.
+    /// 5) `OptimizeScanLoops`
+    ///    This pass turns loops, which move the cell pointer until it reaches
+    ///    a zero cell, into a single scan instruction.
+    ///    It depends on the `OptimizeIncDecPtrChains` pass for strides bigger than one.
+    /// 6) `OptimizeStackOperations`
+    ///    This pass removes stack operations which cancel each other out.
+    /// 7) `OptimizePrintStrings`
+    ///    This pass turns the loops, which print a string pushed right before,
+    ///    into a single print instruction.
+    /// 8) `OptimizeConstants`
+    ///    This pass computes cell values, which are known at compile time.
+    ///    It runs after the loop passes, which leave behind instructions
+    ///    that are known to end on a zero cell.
+    /// 9) `OptimizeDeadCode`
+    ///    This pass removes loops which can never be entered.
+    ///    It runs after the loop passes for the same reason.
+    /// 10) `OptimizeOffsets`
+    ///    This pass removes the remaining pointer movement between instructions
+    ///    by addressing cells relative to the cell pointer.
+    ///    It runs last, because the loop passes only recognize plain pointer movement.
+    pub fn all() -> &'static [OptimizerPass] {
+        const ALL: [OptimizerPass; 10] = [OptimizerPass::OptimizeIncDecPtrChains,
+                                          OptimizerPass::OptimizeIncDecValChains,
+                                          OptimizerPass::OptimizeClearLoops,
+                                          OptimizerPass::OptimizeMultiplyLoops,
+                                          OptimizerPass::OptimizeScanLoops,
+                                          OptimizerPass::OptimizeStackOperations,
+                                          OptimizerPass::OptimizePrintStrings,
+                                          OptimizerPass::OptimizeConstants,
+                                          OptimizerPass::OptimizeDeadCode,
+                                          OptimizerPass::OptimizeOffsets];
+        &ALL
+    }
+
+    /// Gets the short name of the pass, as used by the `--pass` command-line option.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            OptimizerPass::OptimizeClearLoops => "clear-loops",
+            OptimizerPass::OptimizeIncDecValChains => "inc-dec-val-chains",
+            OptimizerPass::OptimizeIncDecPtrChains => "inc-dec-ptr-chains",
+            OptimizerPass::OptimizeMultiplyLoops => "multiply-loops",
+            OptimizerPass::OptimizeScanLoops => "scan-loops",
+            OptimizerPass::OptimizeOffsets => "offsets",
+            OptimizerPass::OptimizeStackOperations => "stack-operations",
+            OptimizerPass::OptimizePrintStrings => "print-strings",
+            OptimizerPass::OptimizeDeadCode => "dead-code",
+            OptimizerPass::OptimizeConstants => "constants",
+        }
+    }
+
+    /// Gets the pass with the short name. See `OptimizerPass::name`.
+    pub fn from_name(name: &str) -> Option<OptimizerPass> {
+        OptimizerPass::all().iter().cloned().find(|pass| pass.name() == name)
+    }
+
+    /// Tests if the pass preserves the behaviour of programs using the cell configuration.
+    ///
+    /// Passes, which do not, are skipped by the `Optimizer`.
+    pub fn supports(&self, cell_config: &CellConfig) -> bool {
+        match *self {
+            OptimizerPass::OptimizeMultiplyLoops => {
+                cell_config.width.min_value() == 0 &&
+                (cell_config.edge == TapeEdge::Grow || cell_config.edge == TapeEdge::Error)
+            }
+            OptimizerPass::OptimizeOffsets => cell_config.edge == TapeEdge::Grow,
+            _ => true,
+        }
+    }
 }
 
 /// The `Optimizer` type.
@@ -33,6 +199,18 @@ pub struct Optimizer {
 
     /// The optimized instruction vector.
     out_instructions: Vec<Instruction>,
+
+    /// The maximum number of iterations.
+    max_iterations: usize,
+
+    /// The number of iterations of the last optimization.
+    iterations: usize,
+
+    /// The statistics of every optimization pass.
+    statistics: Vec<PassStatistics>,
+
+    /// The cell configuration the instructions are optimized for.
+    cell_config: CellConfig,
 }
 
 /// The `OptimizerPassState` type.
@@ -54,6 +232,10 @@ impl Optimizer {
             passes: passes.unwrap_or(Vec::new()),
             instructions: instructions.clone(),
             out_instructions: Vec::with_capacity(instructions.len()),
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            iterations: 0,
+            statistics: Vec::new(),
+            cell_config: CellConfig::new(),
         }
     }
 
@@ -62,33 +244,220 @@ impl Optimizer {
         self.passes.push(pass);
     }
 
+    /// Gets the optimization passes.
+    pub fn passes(&self) -> &[OptimizerPass] {
+        &self.passes
+    }
+
+    /// Sets the cell configuration the instructions are optimized for.
+    ///
+    /// Defaults to `CellConfig::new()`. See `OptimizerPass::supports` for details.
+    pub fn set_cell_config(&mut self, cell_config: CellConfig) {
+        self.cell_config = cell_config;
+    }
+
+    /// Sets the maximum number of iterations.
+    ///
+    /// Protects against passes, which keep undoing each other's work.
+    /// Defaults to 16.
+    pub fn set_max_iterations(&mut self, max_iterations: usize) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Gets the number of iterations of the last optimization.
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Gets the statistics of every optimization pass, in the order the passes were added.
+    pub fn statistics(&self) -> &[PassStatistics] {
+        &self.statistics
+    }
+
     /// Runs the specified optimizations.
-    pub fn optimize(&mut self, iterations: usize) {
-        if self.passes.len() == 0 || iterations == 0 {
-            return;
-        }
-        for _ in 0..iterations {
-            for pass in self.passes.clone() {
-                let mut state = self.create_pass_state();
-                while state.can_advance(0) {
-                    let changed = match pass {
-                        OptimizerPass::OptimizeClearLoops => self.optimize_clear_loops(&mut state),
-                        OptimizerPass::OptimizeIncDecValChains => {
-                            self.optimize_inc_dec_val_chains(&mut state)
-                        }
-                        OptimizerPass::OptimizeIncDecPtrChains => {
-                            self.optimize_inc_dec_ptr_chains(&mut state)
+    ///
+    /// Runs all passes again and again, until none of them changes anything
+    /// or the maximum number of iterations is reached.
+    pub fn optimize(&mut self) {
+        self.run_passes(|_, _, _| Ok(())).ok();
+    }
+
+    /// Runs the specified optimizations, testing every pass for changes in behaviour.
+    ///
+    /// Runs the instructions before and after every pass on the same input,
+    /// comparing the output, the exit code, the memory and the stack.
+    /// The `setup` function configures the virtual machines, e.g. the cell configuration;
+    /// it should set a tick limit, as programs, which exceed a limit, are not tested.
+    /// If the program exceeds a limit before it is optimized, the passes run without
+    /// being validated and `Validation::Skipped` is returned.
+    ///
+    /// Stops at the first pass, which changes the behaviour of the program,
+    /// keeping the instructions from before that pass.
+    pub fn optimize_validated<F>(&mut self,
+                                 input: &[u8],
+                                 setup: F)
+                                 -> Result<Validation, Divergence>
+        where F: Fn(&mut VirtualMachine)
+    {
+        let expected = match Behaviour::record(self.instructions.clone(), input, &setup) {
+            Ok(expected) => expected,
+            Err(limit) => {
+                self.optimize();
+                return Ok(Validation::Skipped(limit));
+            }
+        };
+        let mut validated = self.instructions.clone();
+        self.run_passes(|optimizer, pass, rewrites| {
+
+            // Test if the behaviour changed
+            let behaviour = Behaviour::record(optimizer.instructions.clone(), input, &setup).ok();
+            let difference = match behaviour.and_then(|behaviour| behaviour.compare(&expected)) {
+                Some(difference) => difference,
+                None => {
+                    validated = optimizer.instructions.clone();
+                    return Ok(());
+                }
+            };
+
+            // Find the first rewrite, which changes the behaviour
+            // Rewrites are applied one after another, until the behaviour changes.
+            let mut divergence = None;
+            for limit in 1..(rewrites + 1) {
+                optimizer.instructions = validated.clone();
+                let (_, instr) = optimizer.run_pass(pass, Some(limit));
+                let behaviour = Behaviour::record(optimizer.instructions.clone(), input, &setup)
+                    .ok();
+                if let Some((reason, position)) = behaviour.and_then(|behaviour| {
+                    behaviour.compare(&expected)
+                }) {
+                    divergence = instr.map(|instr| {
+                        Divergence {
+                            pass: pass,
+                            position: position.unwrap_or((instr.line, instr.pos)),
+                            instr: instr,
+                            reason: reason,
                         }
-                    };
-                    if !changed {
-                        self.out_instructions.push(self.instructions[state.pos].clone());
-                        state.skip(1);
-                    }
+                    });
+                    break;
+                }
+            }
+
+            // Restore the instructions from before the pass
+            optimizer.instructions = validated.clone();
+            let instr = optimizer.instructions[0].clone();
+            Err(divergence.unwrap_or(Divergence {
+                pass: pass,
+                position: difference.1.unwrap_or((instr.line, instr.pos)),
+                instr: instr,
+                reason: difference.0,
+            }))
+        })?;
+        Ok(Validation::Validated)
+    }
+
+    /// Runs the passes until none of them changes anything
+    /// or the maximum number of iterations is reached.
+    ///
+    /// The `check` function is called after every pass, which applied any rewrites.
+    /// Does nothing if the jump table of the instructions cannot be built.
+    fn run_passes<F>(&mut self, mut check: F) -> Result<(), Divergence>
+        where F: FnMut(&mut Optimizer, OptimizerPass, usize) -> Result<(), Divergence>
+    {
+        self.statistics = self.passes.iter().map(|&pass| PassStatistics::new(pass)).collect();
+        self.iterations = 0;
+
+        // Leave programs, which cannot be loaded, untouched
+        // Otherwise a pass could remove the offending instruction, e.g. a `!` after `q`,
+        // instead of the virtual machine reporting it.
+        if VirtualMachine::build_jump_table(&self.instructions).is_err() {
+            return Ok(());
+        }
+        while self.iterations < self.max_iterations {
+            self.iterations += 1;
+            let mut changed = false;
+            for i in 0..self.passes.len() {
+                let pass = self.passes[i];
+                let started = Instant::now();
+                let size = self.instructions.len();
+                let (rewrites, _) = self.run_pass(pass, None);
+
+                // Update the statistics
+                {
+                    let statistics = &mut self.statistics[i];
+                    statistics.runs += 1;
+                    statistics.rewrites += rewrites;
+                    statistics.removed += size.saturating_sub(self.instructions.len());
+                    statistics.duration += started.elapsed();
+                }
+                if rewrites > 0 {
+                    check(self, pass, rewrites)?;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a single optimization pass.
+    ///
+    /// Applies at most `limit` rewrites, if specified.
+    /// Returns the number of rewrites applied,
+    /// along with the first instruction replaced by the last rewrite.
+    fn run_pass(&mut self,
+                pass: OptimizerPass,
+                limit: Option<usize>)
+                -> (usize, Option<Instruction>) {
+        if !pass.supports(&self.cell_config) {
+            return (0, None);
+        }
+        let mut rewrites = 0;
+        let mut last_rewrite = None;
+        let mut state = self.create_pass_state();
+        while state.can_advance(0) {
+            let pos = state.pos;
+            let changed = match limit {
+                Some(limit) if rewrites >= limit => false,
+                _ => self.rewrite(pass, &mut state),
+            };
+            match changed {
+                true => {
+                    rewrites += 1;
+                    last_rewrite = Some(pos);
+                }
+                false => {
+                    self.out_instructions.push(self.instructions[state.pos].clone());
+                    state.skip(1);
                 }
-                self.instructions = self.out_instructions.clone();
-                self.out_instructions.clear();
             }
         }
+
+        // Swap the instruction vectors
+        // That way, the allocation of the old instructions is reused for the next pass.
+        mem::swap(&mut self.instructions, &mut self.out_instructions);
+        let instr = last_rewrite.map(|pos| self.out_instructions[pos].clone());
+        self.out_instructions.clear();
+        (rewrites, instr)
+    }
+
+    /// Tries to apply a rewrite of the pass at the current position.
+    ///
+    /// Returns `true` if the pass rewrote any instructions.
+    fn rewrite(&mut self, pass: OptimizerPass, state: &mut OptimizerPassState) -> bool {
+        match pass {
+            OptimizerPass::OptimizeClearLoops => self.optimize_clear_loops(state),
+            OptimizerPass::OptimizeIncDecValChains => self.optimize_inc_dec_val_chains(state),
+            OptimizerPass::OptimizeIncDecPtrChains => self.optimize_inc_dec_ptr_chains(state),
+            OptimizerPass::OptimizeMultiplyLoops => self.optimize_multiply_loops(state),
+            OptimizerPass::OptimizeScanLoops => self.optimize_scan_loops(state),
+            OptimizerPass::OptimizeOffsets => self.optimize_offsets(state),
+            OptimizerPass::OptimizeStackOperations => self.optimize_stack_operations(state),
+            OptimizerPass::OptimizePrintStrings => self.optimize_print_strings(state),
+            OptimizerPass::OptimizeDeadCode => self.optimize_dead_code(state),
+            OptimizerPass::OptimizeConstants => self.optimize_constants(state),
+        }
     }
 
     fn optimize_clear_loops(&mut self, state: &mut OptimizerPassState) -> bool {
@@ -97,6 +466,7 @@ impl Optimizer {
             Some(instr) => {
                 if instr[0].is(Opcode::JzCell) &&
                    instr[1].is(Opcode::Dec) &&
+                   instr[1].offset == 0 &&
                    instr[2].is(Opcode::JnzCell) {
                     self.out_instructions
                         .push(Instruction {
@@ -117,22 +487,23 @@ impl Optimizer {
     fn optimize_inc_dec_val_chains(&mut self, state: &mut OptimizerPassState) -> bool {
         let mut incs = 0;
         let mut decs = 0;
+        let mut n = 0;
         let fst = state.peek_at(self, 0).unwrap();
-        while state.can_advance(0) {
-            let current = state.peek_at(self, 0);
-            match current {
-                Some(val) => {
-                    match val.opcode {
-                        Opcode::Inc => incs += val.argument.unwrap_or(1),
-                        Opcode::Dec => decs += val.argument.unwrap_or(1),
-                        _ => break,
-                    }
-                    state.skip(1);
-                }
-                None => break,
+        while let Some(val) = state.peek_at(self, n) {
+            if val.offset != fst.offset {
+                break;
             }
+            match val.opcode {
+                Opcode::Inc => incs += val.argument.unwrap_or(1),
+                Opcode::Dec => decs += val.argument.unwrap_or(1),
+                _ => break,
+            }
+            n += 1;
         }
-        if incs != 0 || decs != 0 {
+
+        // Test if there is a chain to collapse
+        if n > 1 {
+            state.skip(n);
             self.out_instructions.push(Instruction {
                 value: OPTIMIZED_VALUE.to_string(),
                 opcode: match (incs as i64 - decs as i64) > 0 {
@@ -154,22 +525,20 @@ impl Optimizer {
     fn optimize_inc_dec_ptr_chains(&mut self, state: &mut OptimizerPassState) -> bool {
         let mut incs = 0;
         let mut decs = 0;
+        let mut n = 0;
         let fst = state.peek_at(self, 0).unwrap();
-        while state.can_advance(0) {
-            let current = state.peek_at(self, 0);
-            match current {
-                Some(val) => {
-                    match val.opcode {
-                        Opcode::IncPtr => incs += val.argument.unwrap_or(1),
-                        Opcode::DecPtr => decs += val.argument.unwrap_or(1),
-                        _ => break,
-                    }
-                    state.skip(1);
-                }
-                None => break,
+        while let Some(val) = state.peek_at(self, n) {
+            match val.opcode {
+                Opcode::IncPtr => incs += val.argument.unwrap_or(1),
+                Opcode::DecPtr => decs += val.argument.unwrap_or(1),
+                _ => break,
             }
+            n += 1;
         }
-        if incs != 0 || decs != 0 {
+
+        // Test if there is a chain to collapse
+        if n > 1 {
+            state.skip(n);
             self.out_instructions.push(Instruction {
                 value: OPTIMIZED_VALUE.to_string(),
                 opcode: match (incs as i64 - decs as i64) > 0 {
@@ -188,6 +557,406 @@ impl Optimizer {
         false
     }
 
+    fn optimize_multiply_loops(&mut self, state: &mut OptimizerPassState) -> bool {
+        let fst = state.peek_at(self, 0).unwrap();
+        if !fst.is(Opcode::JzCell) {
+            return false;
+        }
+
+        // Collect the increments and decrements of every cell touched by the loop body
+        // The loop body may only consist of cell value and cell pointer changes.
+        let mut changes: BTreeMap<i64, (i64, i64)> = BTreeMap::new();
+        let mut offset = 0i64;
+        let mut n = 1;
+        loop {
+            let instr = match state.peek_at(self, n) {
+                Some(instr) => instr,
+                None => return false,
+            };
+            let arg = instr.argument.unwrap_or(1) as i64;
+            let target = offset + instr.offset as i64;
+            match instr.opcode {
+                Opcode::Inc => changes.entry(target).or_insert((0, 0)).0 += arg,
+                Opcode::Dec => changes.entry(target).or_insert((0, 0)).1 += arg,
+                Opcode::IncPtr => offset += arg,
+                Opcode::DecPtr => offset -= arg,
+                Opcode::JnzCell => break,
+                _ => return false,
+            }
+            n += 1;
+        }
+
+        // Test if the loop returns to the start cell and decrements it by one
+        // That way, the loop body is executed exactly as often as the value of the start cell.
+        if offset != 0 || changes.get(&0) != Some(&(0, 1)) {
+            return false;
+        }
+
+        // Test if the cells change in a single direction
+        // Otherwise, a value which saturates or overflows in the middle of an iteration
+        // ends up different from the value computed from the net change.
+        if self.cell_config.overflow != Overflow::Wrap &&
+           changes.values().any(|&(incs, decs)| incs != 0 && decs != 0) {
+            return false;
+        }
+
+        // Create the multiplication instructions
+        for (&target, &(incs, decs)) in changes.iter() {
+            let factor = incs - decs;
+            if target == 0 || factor == 0 {
+                continue;
+            }
+            self.out_instructions.push(Instruction {
+                value: OPTIMIZED_VALUE.to_string(),
+                opcode: match factor > 0 {
+                    true => Opcode::MulAdd,
+                    false => Opcode::MulSub,
+                },
+                argument: Some(factor.abs() as u32),
+                offset: target as i32,
+                optimized: true,
+                ..fst.clone()
+            });
+        }
+
+        // Clear the start cell
+        self.out_instructions.push(Instruction {
+            value: OPTIMIZED_VALUE.to_string(),
+            opcode: Opcode::Clear,
+            optimized: true,
+            ..fst
+        });
+        state.skip(n + 1);
+        true
+    }
+
+    fn optimize_scan_loops(&mut self, state: &mut OptimizerPassState) -> bool {
+        let three = state.peek(self, 3);
+        match three {
+            Some(instr) => {
+                if instr[0].is(Opcode::JzCell) &&
+                   (instr[1].is(Opcode::IncPtr) || instr[1].is(Opcode::DecPtr)) &&
+                   instr[2].is(Opcode::JnzCell) {
+                    self.out_instructions
+                        .push(Instruction {
+                            value: OPTIMIZED_VALUE.to_string(),
+                            opcode: match instr[1].opcode {
+                                Opcode::IncPtr => Opcode::ScanRight,
+                                _ => Opcode::ScanLeft,
+                            },
+                            argument: Some(instr[1].argument.unwrap_or(1)),
+                            optimized: true,
+                            ..instr[0].clone()
+                        });
+                    state.skip(3);
+                    return true;
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn optimize_offsets(&mut self, state: &mut OptimizerPassState) -> bool {
+        let fst = state.peek_at(self, 0).unwrap();
+        if !fst.is(Opcode::IncPtr) && !fst.is(Opcode::DecPtr) {
+            return false;
+        }
+
+        // Collect the instructions up to the next instruction,
+        // which depends on the position of the cell pointer
+        let mut offset = 0i64;
+        let mut moves = 0;
+        let mut instructions: Vec<Instruction> = Vec::new();
+        while let Some(instr) = state.peek_at(self, moves + instructions.len()) {
+            match instr.opcode {
+
+                // Accumulate the pointer movement
+                Opcode::IncPtr => offset += instr.argument.unwrap_or(1) as i64,
+                Opcode::DecPtr => offset -= instr.argument.unwrap_or(1) as i64,
+
+                // Address the cell relative to the cell pointer
+                Opcode::Inc | Opcode::Dec | Opcode::Clear | Opcode::Set | Opcode::Push |
+                Opcode::Pop | Opcode::Print => {
+                    instructions.push(Instruction {
+                        offset: instr.offset + offset as i32,
+                        optimized: true,
+                        ..instr
+                    });
+                    continue;
+                }
+
+                // Keep instructions which do not access the memory
+                Opcode::Str | Opcode::Dup | Opcode::Swap | Opcode::ChrMod | Opcode::IntMod |
+                Opcode::BinMod | Opcode::HexMod => {
+                    instructions.push(instr);
+                    continue;
+                }
+
+                // Stop at any other instruction
+                _ => break,
+            }
+            moves += 1;
+        }
+
+        // Test if removing the pointer movement pays off
+        if moves < 2 {
+            return false;
+        }
+        state.skip(moves + instructions.len());
+        self.out_instructions.extend(instructions);
+
+        // Move the cell pointer
+        if offset != 0 {
+            self.out_instructions.push(Instruction {
+                value: OPTIMIZED_VALUE.to_string(),
+                opcode: match offset > 0 {
+                    true => Opcode::IncPtr,
+                    false => Opcode::DecPtr,
+                },
+                argument: Some(offset.abs() as u32),
+                optimized: true,
+                ..fst
+            });
+        }
+        true
+    }
+
+    fn optimize_stack_operations(&mut self, state: &mut OptimizerPassState) -> bool {
+        let two = state.peek(self, 2);
+        match two {
+            Some(instr) => {
+
+                // Pushing a cell and popping it into the same cell does nothing
+                if instr[0].is(Opcode::Push) && instr[1].is(Opcode::Pop) &&
+                   instr[0].offset == instr[1].offset {
+                    state.skip(2);
+                    return true;
+                }
+
+                // Swapping twice does nothing
+                if instr[0].is(Opcode::Swap) && instr[1].is(Opcode::Swap) {
+                    state.skip(2);
+                    return true;
+                }
+
+                // Duplicating and popping the top value
+                // assigns it to the cell without changing the stack
+                if instr[0].is(Opcode::Dup) && instr[1].is(Opcode::Pop) {
+                    self.out_instructions.push(Instruction {
+                        value: OPTIMIZED_VALUE.to_string(),
+                        opcode: Opcode::Peek,
+                        offset: instr[1].offset,
+                        optimized: true,
+                        ..instr[0].clone()
+                    });
+                    state.skip(2);
+                    return true;
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn optimize_print_strings(&mut self, state: &mut OptimizerPassState) -> bool {
+        let six = state.peek(self, 6);
+        match six {
+            Some(instr) => {
+                // Strings containing a zero value are only printed up to it,
+                // which is why they are left untouched.
+                if instr[0].is(Opcode::Str) && !instr[0].value.contains('\0') &&
+                   instr[1].is(Opcode::JzStack) &&
+                   instr[2].is(Opcode::Pop) &&
+                   instr[3].is(Opcode::Print) &&
+                   instr[4].is(Opcode::JnzStack) &&
+                   instr[5].is(Opcode::Pop) &&
+                   instr[2..6].iter().all(|instr| instr.offset == 0) {
+                    self.out_instructions
+                        .push(Instruction {
+                            opcode: Opcode::PrintStr,
+                            optimized: true,
+                            ..instr[0].clone()
+                        });
+                    state.skip(6);
+                    return true;
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn optimize_dead_code(&mut self, state: &mut OptimizerPassState) -> bool {
+        let fst = state.peek_at(self, 0).unwrap();
+        match fst.opcode {
+
+            // Remove loops which can never be entered
+            Opcode::JzCell | Opcode::JzStack => {
+
+                // Test if the loop condition is known to be zero
+                // This is the case at the start of the program, where all cells
+                // are zero and the stack is empty, and after instructions,
+                // which only continue once the cell or the top of the stack is zero.
+                // A loop, which contains a break, may also be left with a non-zero value.
+                let dead = match self.out_instructions.last() {
+                    None => true,
+                    Some(prev) => {
+                        match (fst.opcode, prev.opcode) {
+                            (Opcode::JzCell, Opcode::JnzCell) |
+                            (Opcode::JzStack, Opcode::JnzStack) => !self.last_loop_breaks(),
+                            (Opcode::JzCell, Opcode::ScanLeft) |
+                            (Opcode::JzCell, Opcode::ScanRight) => true,
+                            (Opcode::JzCell, Opcode::Clear) => prev.offset == 0,
+                            _ => false,
+                        }
+                    }
+                };
+                if !dead {
+                    return false;
+                }
+
+                // Remove the loop, including its body
+                match self.find_block_end(state, 1) {
+                    Some(n) if state.peek_at(self, n).is_some() => {
+                        state.skip(n + 1);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+
+            // Remove the instructions which can never be reached,
+            // up to the end of the enclosing loop or the end of the program
+            Opcode::Terminate | Opcode::Break => {
+                match self.find_block_end(state, 1) {
+                    Some(n) if n > 1 => {
+                        self.out_instructions.push(fst);
+                        state.skip(n);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn optimize_constants(&mut self, state: &mut OptimizerPassState) -> bool {
+        let fst = state.peek_at(self, 0).unwrap();
+
+        // Find the cell with the known value
+        // Either the first instruction sets the value of a cell,
+        // or the value of the cell is known to be zero at this point.
+        // This is the case at the start of the program, where all cells are zero,
+        // and after instructions, which only continue once the cell is zero.
+        // A loop, which contains a break, may also be left with a non-zero value.
+        let (start, offset, value) = match fst.opcode {
+            Opcode::Clear => (1, fst.offset, 0),
+            Opcode::Set => (1, fst.offset, fst.argument.unwrap_or(0) as i64),
+            _ => {
+                let zero = match self.out_instructions.last() {
+                    None => true,
+                    Some(prev) => {
+                        match prev.opcode {
+                            Opcode::JnzCell => fst.offset == 0 && !self.last_loop_breaks(),
+                            Opcode::ScanLeft | Opcode::ScanRight => fst.offset == 0,
+                            _ => false,
+                        }
+                    }
+                };
+                if !zero {
+                    return false;
+                }
+                (0, fst.offset, 0)
+            }
+        };
+
+        // Compute the value of the cell
+        // Stop before the value leaves the range every cell width can hold.
+        let mut value = value;
+        let mut n = start;
+        while let Some(instr) = state.peek_at(self, n) {
+            if instr.offset != offset {
+                break;
+            }
+            let arg = instr.argument.unwrap_or(1) as i64;
+            let next = match instr.opcode {
+                Opcode::Inc => value + arg,
+                Opcode::Dec => value - arg,
+                Opcode::Double => value * 2,
+                Opcode::Halve => value / 2,
+                _ => break,
+            };
+            if next < 0 || next > u8::max_value() as i64 {
+                break;
+            }
+            value = next;
+            n += 1;
+        }
+
+        // Test if replacing the instructions pays off
+        if n < 2 {
+            return false;
+        }
+        state.skip(n);
+        self.out_instructions.push(Instruction {
+            value: OPTIMIZED_VALUE.to_string(),
+            opcode: match value {
+                0 => Opcode::Clear,
+                _ => Opcode::Set,
+            },
+            argument: match value {
+                0 => None,
+                _ => Some(value as u32),
+            },
+            offset: offset,
+            optimized: true,
+            ..fst
+        });
+        true
+    }
+
+    /// Finds the end of the block starting at the `n`-th instruction relative to `state.pos`.
+    ///
+    /// The block ends at the first unmatched loop end or at the end of the program.
+    /// Returns the relative position of the end, or `None` if a loop in the block is unmatched.
+    fn find_block_end(&mut self, state: &OptimizerPassState, n: usize) -> Option<usize> {
+        let mut depth = 0;
+        let mut n = n;
+        while let Some(instr) = state.peek_at(self, n) {
+            match instr.opcode {
+                Opcode::JzCell | Opcode::JzStack => depth += 1,
+                Opcode::JnzCell | Opcode::JnzStack if depth == 0 => return Some(n),
+                Opcode::JnzCell | Opcode::JnzStack => depth -= 1,
+                _ => (),
+            }
+            n += 1;
+        }
+        match depth {
+            0 => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Tests if the loop ending at the last optimized instruction contains a break,
+    /// which leaves the loop without testing its condition.
+    ///
+    /// Breaks inside of nested loops only leave the nested loop and are ignored.
+    fn last_loop_breaks(&self) -> bool {
+        let mut depth = 0;
+        for instr in self.out_instructions.iter().rev().skip(1) {
+            match instr.opcode {
+                Opcode::JnzCell | Opcode::JnzStack => depth += 1,
+                Opcode::JzCell | Opcode::JzStack if depth == 0 => return false,
+                Opcode::JzCell | Opcode::JzStack => depth -= 1,
+                Opcode::Break if depth == 0 => return true,
+                _ => (),
+            }
+        }
+        false
+    }
+
     fn create_pass_state(&self) -> OptimizerPassState {
         OptimizerPassState {
             pos: 0,
@@ -0,0 +1,51 @@
+use std::fmt::{Display, Formatter, Result};
+use std::time::Duration;
+use optimizer::OptimizerPass;
+
+/// The `PassStatistics` type.
+///
+/// Describes the work done by an optimization pass, summed up over all iterations.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PassStatistics {
+    /// The optimization pass.
+    pub pass: OptimizerPass,
+
+    /// The number of times the pass was ran.
+    pub runs: usize,
+
+    /// The number of rewrites applied by the pass.
+    pub rewrites: usize,
+
+    /// The number of instructions removed by the pass.
+    pub removed: usize,
+
+    /// The time spent running the pass.
+    pub duration: Duration,
+}
+
+/// The `PassStatistics` implementation.
+impl PassStatistics {
+    /// Constructs new empty `PassStatistics` for the pass.
+    pub fn new(pass: OptimizerPass) -> PassStatistics {
+        PassStatistics {
+            pass: pass,
+            runs: 0,
+            rewrites: 0,
+            removed: 0,
+            duration: Duration::new(0, 0),
+        }
+    }
+}
+
+/// Implements the `Display` trait for the `PassStatistics` type.
+impl Display for PassStatistics {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f,
+               "{:?}: {} runs, {} rewrites, {} instructions removed, {:.3}ms",
+               self.pass,
+               self.runs,
+               self.rewrites,
+               self.removed,
+               self.duration.as_secs_f64() * 1000.0)
+    }
+}
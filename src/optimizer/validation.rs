@@ -0,0 +1,191 @@
+use std::fmt::{Display, Formatter, Result};
+use std::io::Cursor;
+use std::mem::{self, Discriminant};
+use optimizer::OptimizerPass;
+use vm::{SharedBuffer, VirtualMachine, ExecutionStatus, VmError, Limit};
+use vm::instruction::Instruction;
+
+/// The `Divergence` type.
+///
+/// Describes an optimization pass, which changed the behaviour of the program.
+/// See `Optimizer::optimize_validated` for details.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Divergence {
+    /// The optimization pass responsible.
+    pub pass: OptimizerPass,
+
+    /// The first instruction of the rewrite responsible.
+    pub instr: Instruction,
+
+    /// The line and the position in the source, where the behaviour first differs.
+    ///
+    /// Falls back to the source position of `instr`, if the difference
+    /// cannot be traced back to an instruction, e.g. a difference in the memory.
+    pub position: (u32, u32),
+
+    /// A description of the difference in behaviour.
+    pub reason: String,
+}
+
+/// The `Validation` type.
+///
+/// Describes whether the optimization passes were validated.
+/// See `Optimizer::optimize_validated` for details.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Validation {
+    /// No optimization pass changed the behaviour of the program.
+    Validated,
+
+    /// The program exceeded an execution limit before it was optimized,
+    /// so the passes ran without being validated.
+    Skipped(Limit),
+}
+
+/// The `Behaviour` type.
+///
+/// The observable behaviour of a program.
+pub struct Behaviour {
+    /// The output.
+    output: Vec<u8>,
+
+    /// The source position of the instruction, which printed each byte of the output.
+    printed_at: Vec<(u32, u32)>,
+
+    /// The source position of the last instruction executed; `None` if there was none.
+    ended_at: Option<(u32, u32)>,
+
+    /// The exit code, or the kind and the message of the error.
+    result: ::std::result::Result<i32, (Discriminant<VmError>, String)>,
+
+    /// The non-zero cells of the memory as `(index, value)` pairs, ordered by index.
+    memory: Vec<(i64, i64)>,
+
+    /// The stack.
+    stack: Vec<i64>,
+}
+
+/// The `Behaviour` implementation.
+impl Behaviour {
+    /// Runs the instructions and records their behaviour.
+    ///
+    /// Returns the limit if an execution limit was exceeded,
+    /// since the behaviour is unknown in that case.
+    pub fn record<F>(instructions: Vec<Instruction>,
+                     input: &[u8],
+                     setup: &F)
+                     -> ::std::result::Result<Behaviour, Limit>
+        where F: Fn(&mut VirtualMachine)
+    {
+
+        // Create the virtual machine
+        let output = SharedBuffer::new();
+        let mut vm = VirtualMachine::new(instructions, None, None);
+        setup(&mut vm);
+        vm.set_input(Box::new(Cursor::new(input.to_vec())));
+        vm.set_output(Box::new(output.clone()));
+
+        // Run the program
+        // Every instruction is executed separately, so that the output
+        // can be traced back to the instructions, which printed it.
+        let mut printed_at = Vec::new();
+        let mut ended_at = None;
+        let result = loop {
+            if let Some(instr) = vm.current_instruction() {
+                ended_at = Some((instr.line, instr.pos));
+            }
+            let status = vm.step();
+            if let Some(position) = ended_at {
+                printed_at.resize(output.len(), position);
+            }
+            match status {
+                Ok(ExecutionStatus::Running) => (),
+                Ok(ExecutionStatus::Halted(code)) => break Ok(code),
+                Err(VmError::LimitExceeded(_, limit)) => return Err(limit),
+                Err(err) => break Err((mem::discriminant(&err), err.to_string())),
+            }
+        };
+        Ok(Behaviour {
+            output: output.contents(),
+            printed_at: printed_at,
+            ended_at: ended_at,
+            result: result,
+            memory: vm.tape().cells(),
+            stack: vm.stack().to_vec(),
+        })
+    }
+
+    /// Compares the behaviour with the expected behaviour.
+    ///
+    /// Returns a description of the first difference, if any, along with the source position
+    /// of the instruction, which caused it, if known.
+    pub fn compare(&self, expected: &Behaviour) -> Option<(String, Option<(u32, u32)>)> {
+
+        // Compare the output
+        // A missing byte is attributed to the instruction the program ended at.
+        if self.output != expected.output {
+            let offset = self.output
+                .iter()
+                .zip(expected.output.iter())
+                .take_while(|&(a, b)| a == b)
+                .count();
+            let position = self.printed_at.get(offset).cloned().or(self.ended_at);
+            return Some((format!("Output differs at byte {}", offset), position));
+        }
+
+        // Compare the exit code or the error
+        let reason = match (&self.result, &expected.result) {
+            (&Ok(code), &Ok(expected)) if code != expected => {
+                Some(format!("Exit code {} instead of {}", code, expected))
+            }
+            (&Ok(_), &Err((_, ref expected))) => {
+                Some(format!("No error instead of '{}'", expected))
+            }
+            (&Err((_, ref err)), &Ok(_)) => Some(format!("Unexpected error '{}'", err)),
+            (&Err((kind, ref err)), &Err((expected_kind, ref expected))) => {
+                match kind != expected_kind {
+                    true => Some(format!("Error '{}' instead of '{}'", err, expected)),
+                    false => None,
+                }
+            }
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            return Some((reason, self.ended_at));
+        }
+
+        // Compare the memory
+        if self.memory != expected.memory {
+            let index = self.memory
+                .iter()
+                .zip(expected.memory.iter())
+                .find(|&(a, b)| a != b)
+                .map(|(&(index, _), &(expected, _))| if index < expected { index } else { expected })
+                .or_else(|| self.memory.get(expected.memory.len()).map(|&(index, _)| index))
+                .or_else(|| expected.memory.get(self.memory.len()).map(|&(index, _)| index))
+                .unwrap_or(0);
+            return Some((format!("Memory differs at cell {}", index), None));
+        }
+
+        // Compare the stack
+        if self.stack != expected.stack {
+            let reason = format!("Stack differs ({} items instead of {})",
+                                 self.stack.len(),
+                                 expected.stack.len());
+            return Some((reason, None));
+        }
+        None
+    }
+}
+
+/// Implements the `Display` trait for the `Divergence` type.
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f,
+               "{} at ({}:{}) after {:?} rewrote the instructions at {}",
+               self.reason,
+               self.position.0,
+               self.position.1,
+               self.pass,
+               self.instr)
+    }
+}
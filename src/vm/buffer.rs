@@ -0,0 +1,56 @@
+use std::io::{Result, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The `SharedBuffer` type.
+///
+/// An in-memory output stream. Clones share the same buffer,
+/// which makes the output accessible after handing a clone to a `VirtualMachine`,
+/// even one running on another thread.
+#[derive(Clone, Default)]
+pub struct SharedBuffer {
+    /// The written bytes.
+    buffer: Arc<Mutex<Vec<u8>>>,
+}
+
+/// The `SharedBuffer` implementation.
+impl SharedBuffer {
+    /// Constructs a new `SharedBuffer`.
+    pub fn new() -> SharedBuffer {
+        SharedBuffer { buffer: Arc::new(Mutex::new(Vec::new())) }
+    }
+
+    /// Gets a copy of the written bytes.
+    pub fn contents(&self) -> Vec<u8> {
+        self.lock().clone()
+    }
+
+    /// Gets the number of written bytes.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Tests if nothing has been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Locks the buffer.
+    ///
+    /// A writer, which panicked, cannot leave the bytes in an invalid state,
+    /// which is why the buffer stays usable.
+    fn lock<'a>(&'a self) -> MutexGuard<'a, Vec<u8>> {
+        self.buffer.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Implements the `Write` trait for the `SharedBuffer` type.
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
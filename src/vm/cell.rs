@@ -0,0 +1,127 @@
+/// The `CellWidth` type.
+#[derive(Copy, Clone, RustcEncodable, RustcDecodable, PartialEq, Eq, Debug)]
+pub enum CellWidth {
+    /// Unsigned 8-bit cells, ranging from 0 to 255.
+    U8,
+
+    /// Unsigned 16-bit cells, ranging from 0 to 65535.
+    U16,
+
+    /// Unsigned 32-bit cells, ranging from 0 to 4294967295.
+    U32,
+
+    /// Signed 64-bit cells.
+    I64,
+}
+
+/// The `Overflow` type.
+///
+/// Describes what happens if a cell value leaves the range of the `CellWidth`.
+#[derive(Copy, Clone, RustcEncodable, RustcDecodable, PartialEq, Eq, Debug)]
+pub enum Overflow {
+    /// The value wraps around, e.g. `255 + 1 = 0` for `CellWidth::U8`.
+    Wrap,
+
+    /// The value is clamped to the range, e.g. `0 - 1 = 0` for `CellWidth::U8`.
+    Saturate,
+
+    /// Execution stops with `VmError::CellOverflow`.
+    Error,
+}
+
+/// The `TapeEdge` type.
+///
+/// Describes what happens if the cell pointer is moved past the edge of the memory.
+#[derive(Copy, Clone, RustcEncodable, RustcDecodable, PartialEq, Eq, Debug)]
+pub enum TapeEdge {
+    /// Execution stops with `VmError::MemoryOutOfBounds`.
+    Error,
+
+    /// The cell pointer stays at the edge.
+    Clamp,
+
+    /// The cell pointer wraps around to the other edge.
+    Wrap,
+
+    /// The memory grows to make room for the cell.
+    Grow,
+}
+
+/// The `CellConfig` type.
+#[derive(Copy, Clone, RustcEncodable, RustcDecodable, PartialEq, Eq, Debug)]
+pub struct CellConfig {
+    /// The cell width. See `CellWidth` for details.
+    pub width: CellWidth,
+
+    /// The overflow behaviour. See `Overflow` for details.
+    pub overflow: Overflow,
+
+    /// The tape edge behaviour. See `TapeEdge` for details.
+    pub edge: TapeEdge,
+}
+
+/// The `CellWidth` implementation.
+impl CellWidth {
+    /// Gets the smallest value a cell can hold.
+    pub fn min_value(&self) -> i64 {
+        match *self {
+            CellWidth::I64 => i64::min_value(),
+            _ => 0,
+        }
+    }
+
+    /// Gets the biggest value a cell can hold.
+    pub fn max_value(&self) -> i64 {
+        match *self {
+            CellWidth::U8 => u8::max_value() as i64,
+            CellWidth::U16 => u16::max_value() as i64,
+            CellWidth::U32 => u32::max_value() as i64,
+            CellWidth::I64 => i64::max_value(),
+        }
+    }
+}
+
+/// The `CellConfig` implementation.
+impl CellConfig {
+    /// Constructs a new `CellConfig`.
+    ///
+    /// Uses saturating 32-bit cells on a growing tape.
+    pub fn new() -> CellConfig {
+        CellConfig {
+            width: CellWidth::U32,
+            overflow: Overflow::Saturate,
+            edge: TapeEdge::Grow,
+        }
+    }
+
+    /// Fits a value into the range of the cell width.
+    ///
+    /// Returns `None` if the value is out of range
+    /// and the overflow behaviour is `Overflow::Error`.
+    pub fn fit(&self, value: i128) -> Option<i64> {
+        let min = self.width.min_value() as i128;
+        let max = self.width.max_value() as i128;
+        if value >= min && value <= max {
+            return Some(value as i64);
+        }
+        match self.overflow {
+            Overflow::Wrap => {
+                let range = max - min + 1;
+                let mut wrapped = (value - min) % range;
+                if wrapped < 0 {
+                    wrapped += range;
+                }
+                Some((wrapped + min) as i64)
+            }
+            Overflow::Saturate => Some(if value < min { min } else { max } as i64),
+            Overflow::Error => None,
+        }
+    }
+}
+
+/// Implements the `Default` trait for the `CellConfig` type.
+impl Default for CellConfig {
+    fn default() -> CellConfig {
+        CellConfig::new()
+    }
+}
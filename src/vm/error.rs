@@ -0,0 +1,126 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result};
+use std::io;
+use vm::instruction::Instruction;
+use vm::limits::Limit;
+
+/// The `ErrorContext` type.
+///
+/// Describes the state of the virtual machine at the time an error occurred.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ErrorContext {
+    /// The offending instruction; `None` if the error occurred after the last instruction,
+    /// e.g. while inspecting a halted program.
+    pub instr: Option<Instruction>,
+
+    /// The instruction pointer.
+    pub ip: usize,
+
+    /// The cell pointer.
+    pub cp: i64,
+
+    /// The ticks; more specifically the cycle count.
+    pub ticks: u64,
+}
+
+/// The `VmError` type.
+#[derive(Debug)]
+pub enum VmError {
+    /// A loop instruction has no matching counterpart.
+    UnmatchedLoop(ErrorContext),
+
+    /// A loop control instruction is not inside of a loop.
+    OutsideOfLoop(ErrorContext),
+
+    /// An instruction needs more values than there are on the stack.
+    StackUnderflow(ErrorContext),
+
+    /// The cell pointer was moved outside of the memory.
+    MemoryOutOfBounds(ErrorContext),
+
+    /// The value of the cell left the range of the cell width.
+    CellOverflow(ErrorContext),
+
+    /// The value of the cell is not a valid character.
+    InvalidCharacter(ErrorContext, i64),
+
+    /// Reading from or writing to a stream failed.
+    Io(ErrorContext, io::Error),
+
+    /// An execution limit was exceeded. See `ExecutionLimits` for details.
+    LimitExceeded(ErrorContext, Limit),
+}
+
+/// The `VmError` implementation.
+impl VmError {
+    /// Gets the state of the virtual machine at the time the error occurred.
+    pub fn context(&self) -> &ErrorContext {
+        match *self {
+            VmError::UnmatchedLoop(ref ctx) |
+            VmError::OutsideOfLoop(ref ctx) |
+            VmError::StackUnderflow(ref ctx) |
+            VmError::MemoryOutOfBounds(ref ctx) |
+            VmError::CellOverflow(ref ctx) |
+            VmError::InvalidCharacter(ref ctx, _) |
+            VmError::Io(ref ctx, _) |
+            VmError::LimitExceeded(ref ctx, _) => ctx,
+        }
+    }
+}
+
+/// Implements the `Display` trait for the `ErrorContext` type.
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self.instr {
+            Some(ref instr) => write!(f, "{}", instr)?,
+            None => write!(f, "the end of the program")?,
+        }
+        write!(f, " [ip={}, cp={}, ticks={}]", self.ip, self.cp, self.ticks)
+    }
+}
+
+/// The `ErrorContext` implementation.
+impl ErrorContext {
+    /// Gets the value of the offending instruction; empty if there is none.
+    fn value(&self) -> &str {
+        self.instr.as_ref().map_or("", |instr| &instr.value)
+    }
+}
+
+/// Implements the `Display` trait for the `VmError` type.
+impl Display for VmError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match *self {
+            VmError::UnmatchedLoop(ref ctx) => {
+                write!(f, "Unmatched '{}' at {}", ctx.value(), ctx)
+            }
+            VmError::OutsideOfLoop(ref ctx) => {
+                write!(f, "'{}' outside of loop at {}", ctx.value(), ctx)
+            }
+            VmError::StackUnderflow(ref ctx) => {
+                write!(f, "Attempt to pop value off empty stack at {}", ctx)
+            }
+            VmError::MemoryOutOfBounds(ref ctx) => {
+                write!(f, "Cell pointer out of bounds at {}", ctx)
+            }
+            VmError::CellOverflow(ref ctx) => write!(f, "Cell overflow at {}", ctx),
+            VmError::InvalidCharacter(ref ctx, value) => {
+                write!(f, "Invalid character '{}' at {}", value, ctx)
+            }
+            VmError::Io(ref ctx, ref err) => write!(f, "I/O error '{}' at {}", err, ctx),
+            VmError::LimitExceeded(ref ctx, limit) => {
+                write!(f, "{} limit exceeded at {}", limit, ctx)
+            }
+        }
+    }
+}
+
+/// Implements the `Error` trait for the `VmError` type.
+impl Error for VmError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            VmError::Io(_, ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
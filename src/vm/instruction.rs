@@ -19,6 +19,11 @@ pub struct Instruction {
     /// The (optional) argument.
     pub argument: Option<u32>,
 
+    /// The offset of the target cell, relative to the cell pointer.
+    ///
+    /// Only set by the optimizer; see `OptimizerPass::OptimizeOffsets`.
+    pub offset: i32,
+
     /// A value indicating whether the instruction
     /// was modified by one or more optimization passes.
     pub optimized: bool,
@@ -41,6 +46,7 @@ impl Instruction {
             value: value,
             opcode: opcode,
             argument: arg,
+            offset: 0,
             optimized: false,
         }
     }
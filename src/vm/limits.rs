@@ -0,0 +1,65 @@
+use std::fmt::{Display, Formatter, Result};
+use std::time::Duration;
+
+/// The `ExecutionLimits` type.
+///
+/// Limits the resources a program may use. `None` means unlimited.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct ExecutionLimits {
+    /// The maximum number of executed instructions.
+    pub max_ticks: Option<u64>,
+
+    /// The maximum number of items on the stack.
+    pub max_stack_depth: Option<usize>,
+
+    /// The maximum number of cells the memory may grow to.
+    pub max_memory: Option<usize>,
+
+    /// The maximum wall-clock time, measured from the first executed instruction.
+    pub max_duration: Option<Duration>,
+}
+
+/// The `ExecutionLimits` implementation.
+impl ExecutionLimits {
+    /// Constructs new `ExecutionLimits` without any limits.
+    pub fn new() -> ExecutionLimits {
+        ExecutionLimits {
+            max_ticks: None,
+            max_stack_depth: None,
+            max_memory: None,
+            max_duration: None,
+        }
+    }
+}
+
+/// The `Limit` type.
+///
+/// Identifies the limit that was exceeded.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Limit {
+    /// See `ExecutionLimits.max_ticks`.
+    Ticks,
+
+    /// See `ExecutionLimits.max_stack_depth`.
+    StackDepth,
+
+    /// See `ExecutionLimits.max_memory`.
+    Memory,
+
+    /// See `ExecutionLimits.max_duration`.
+    Duration,
+}
+
+/// Implements the `Display` trait for the `Limit` type.
+impl Display for Limit {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f,
+               "{}",
+               match *self {
+                   Limit::Ticks => "Tick",
+                   Limit::StackDepth => "Stack depth",
+                   Limit::Memory => "Memory",
+                   Limit::Duration => "Time",
+               })
+    }
+}
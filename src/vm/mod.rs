@@ -1,4 +1,16 @@
 pub mod opcodes;
 pub mod instruction;
 pub mod vm;
-pub use vm::vm::VirtualMachine;
+pub mod error;
+pub mod buffer;
+pub mod snapshot;
+pub mod limits;
+pub mod cell;
+pub mod tape;
+pub use vm::vm::{VirtualMachine, ExecutionStatus, PrintMode};
+pub use vm::error::{ErrorContext, VmError};
+pub use vm::buffer::SharedBuffer;
+pub use vm::snapshot::Snapshot;
+pub use vm::limits::{ExecutionLimits, Limit};
+pub use vm::cell::{CellConfig, CellWidth, Overflow, TapeEdge};
+pub use vm::tape::Tape;
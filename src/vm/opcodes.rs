@@ -31,4 +31,11 @@ pub enum Opcode {
 
     // Opcodes generated by the optimizer
     Clear,
+    MulAdd, // cell[cp + offset] += cell[cp] * argument
+    MulSub, // cell[cp + offset] -= cell[cp] * argument
+    ScanLeft, // [<]
+    ScanRight, // [>]
+    PrintStr, // "..."(;.);
+    Peek, // &;
+    Set, // cell[cp + offset] = argument
 }
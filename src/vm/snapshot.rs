@@ -0,0 +1,58 @@
+use vm::instruction::Instruction;
+use vm::vm::{ExecutionStatus, PrintMode, VirtualMachine};
+
+/// The `Snapshot` type.
+///
+/// An owned copy of the state of a `VirtualMachine`.
+#[derive(Clone, RustcEncodable, RustcDecodable, PartialEq, Debug)]
+pub struct Snapshot {
+    /// The instruction to be executed next.
+    ///
+    /// `None` if the program has halted.
+    pub instr: Option<Instruction>,
+
+    /// The instruction pointer.
+    pub ip: usize,
+
+    /// The cell pointer.
+    pub cp: i64,
+
+    /// The ticks; more specifically the cycle count.
+    pub ticks: u64,
+
+    /// The non-zero cells of the memory as `(index, value)` pairs, ordered by index.
+    pub memory: Vec<(i64, i64)>,
+
+    /// The index of the first cell and the index behind the last cell of the memory.
+    pub memory_bounds: (i64, i64),
+
+    /// The stack; the last item is the top of the stack.
+    pub stack: Vec<i64>,
+
+    /// The print mode.
+    pub print_mode: PrintMode,
+
+    /// The exit code; set once the program has halted.
+    pub exit_code: Option<i32>,
+}
+
+/// The `Snapshot` implementation.
+impl Snapshot {
+    /// Constructs a new `Snapshot` from the state of a `VirtualMachine`.
+    pub fn new(vm: &VirtualMachine) -> Snapshot {
+        Snapshot {
+            instr: vm.current_instruction().cloned(),
+            ip: vm.ip(),
+            cp: vm.cp(),
+            ticks: vm.ticks(),
+            memory: vm.tape().cells(),
+            memory_bounds: (vm.tape().low(), vm.tape().high()),
+            stack: vm.stack().to_vec(),
+            print_mode: vm.print_mode(),
+            exit_code: match vm.status() {
+                ExecutionStatus::Halted(code) => Some(code),
+                ExecutionStatus::Running => None,
+            },
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::{BTreeMap, VecDeque};
+
+/// The `Storage` type.
+#[derive(Clone, Debug)]
+enum Storage {
+    /// Every cell is stored; the first item is the cell at `Tape.low`.
+    Dense(VecDeque<i64>),
+
+    /// Only non-zero cells are stored.
+    Sparse(BTreeMap<i64, i64>),
+}
+
+/// The `Tape` type.
+///
+/// The memory of the virtual machine. Cells are addressed by signed indices,
+/// which allows the tape to grow in both directions.
+#[derive(Clone, Debug)]
+pub struct Tape {
+    /// The cells.
+    storage: Storage,
+
+    /// The index of the first cell.
+    low: i64,
+
+    /// The index behind the last cell.
+    high: i64,
+}
+
+/// The `Tape` implementation.
+impl Tape {
+    /// Constructs a new `Tape` holding the cells `0..size`.
+    ///
+    /// Every cell is stored, which makes accessing cells fast.
+    pub fn new(size: usize) -> Tape {
+        let mut cells = VecDeque::with_capacity(size);
+        cells.resize(size, 0);
+        Tape {
+            storage: Storage::Dense(cells),
+            low: 0,
+            high: size as i64,
+        }
+    }
+
+    /// Constructs a new sparse `Tape` holding the cells `0..size`.
+    ///
+    /// Only non-zero cells are stored, which makes growing the tape free.
+    /// Useful for programs that move the cell pointer across large distances.
+    pub fn sparse(size: usize) -> Tape {
+        Tape {
+            storage: Storage::Sparse(BTreeMap::new()),
+            low: 0,
+            high: size as i64,
+        }
+    }
+
+    /// Tests if the tape only stores non-zero cells.
+    pub fn is_sparse(&self) -> bool {
+        match self.storage {
+            Storage::Dense(_) => false,
+            Storage::Sparse(_) => true,
+        }
+    }
+
+    /// Gets the index of the first cell.
+    pub fn low(&self) -> i64 {
+        self.low
+    }
+
+    /// Gets the index behind the last cell.
+    pub fn high(&self) -> i64 {
+        self.high
+    }
+
+    /// Gets the number of cells.
+    pub fn len(&self) -> usize {
+        (self.high - self.low) as usize
+    }
+
+    /// Tests if the tape holds no cells.
+    pub fn is_empty(&self) -> bool {
+        self.high == self.low
+    }
+
+    /// Tests if the tape holds the cell at `index`.
+    pub fn contains(&self, index: i64) -> bool {
+        index >= self.low && index < self.high
+    }
+
+    /// Gets the number of cells actually stored.
+    pub fn allocated(&self) -> usize {
+        match self.storage {
+            Storage::Dense(ref cells) => cells.len(),
+            Storage::Sparse(ref cells) => cells.len(),
+        }
+    }
+
+    /// Gets the number of cells that would be stored
+    /// after growing the tape to hold the cell at `index`.
+    ///
+    /// Returns `None` if the tape cannot hold the cell. See `Tape::grow` for details.
+    pub fn allocated_with(&self, index: i64) -> Option<usize> {
+        let (low, high) = self.bounds_with(index)?;
+        match self.storage {
+            Storage::Dense(_) => Some((high - low) as usize),
+            Storage::Sparse(ref cells) => Some(cells.len()),
+        }
+    }
+
+    /// Gets the value of the cell at `index`.
+    ///
+    /// Cells outside of the tape are zero.
+    pub fn get(&self, index: i64) -> i64 {
+        if !self.contains(index) {
+            return 0;
+        }
+        match self.storage {
+            Storage::Dense(ref cells) => cells[(index - self.low) as usize],
+            Storage::Sparse(ref cells) => cells.get(&index).cloned().unwrap_or(0),
+        }
+    }
+
+    /// Sets the value of the cell at `index`.
+    ///
+    /// Grows the tape if it does not hold the cell yet.
+    /// Returns `false` if the tape cannot hold the cell. See `Tape::grow` for details.
+    pub fn set(&mut self, index: i64, value: i64) -> bool {
+        if !self.grow(index) {
+            return false;
+        }
+        match self.storage {
+            Storage::Dense(ref mut cells) => cells[(index - self.low) as usize] = value,
+            Storage::Sparse(ref mut cells) => {
+                match value {
+                    0 => cells.remove(&index),
+                    _ => cells.insert(index, value),
+                };
+            }
+        }
+        true
+    }
+
+    /// Grows the tape to hold the cell at `index`.
+    ///
+    /// Returns `false` if the tape cannot hold the cell,
+    /// because the number of cells would not fit into an `i64`.
+    pub fn grow(&mut self, index: i64) -> bool {
+        let (low, high) = match self.bounds_with(index) {
+            Some(bounds) => bounds,
+            None => return false,
+        };
+        if let Storage::Dense(ref mut cells) = self.storage {
+            for _ in low..self.low {
+                cells.push_front(0);
+            }
+            cells.resize((high - low) as usize, 0);
+        }
+        self.low = low;
+        self.high = high;
+        true
+    }
+
+    /// Gets the bounds of the tape after growing it to hold the cell at `index`.
+    ///
+    /// Returns `None` if the number of cells would not fit into an `i64`.
+    fn bounds_with(&self, index: i64) -> Option<(i64, i64)> {
+        let low = if index < self.low { index } else { self.low };
+        let high = if index >= self.high { index.checked_add(1)? } else { self.high };
+        high.checked_sub(low).map(|_| (low, high))
+    }
+
+    /// Gets the values of the cells `start..end`.
+    pub fn window(&self, start: i64, end: i64) -> Vec<i64> {
+        (start..end).map(|index| self.get(index)).collect()
+    }
+
+    /// Gets the non-zero cells as `(index, value)` pairs, ordered by index.
+    pub fn cells(&self) -> Vec<(i64, i64)> {
+        match self.storage {
+            Storage::Dense(ref cells) => {
+                cells.iter()
+                    .enumerate()
+                    .filter(|&(_, &value)| value != 0)
+                    .map(|(i, &value)| (self.low + i as i64, value))
+                    .collect()
+            }
+            Storage::Sparse(ref cells) => {
+                cells.iter().map(|(&index, &value)| (index, value)).collect()
+            }
+        }
+    }
+}
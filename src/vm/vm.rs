@@ -1,18 +1,52 @@
 use std::collections::BTreeMap;
-use std::cmp::max;
+use std::cmp::{max, min};
 use std::char;
-use std::io::{self, Read, Write};
+use std::io::{self, Cursor, Read, Write};
+use std::time::Instant;
+use vm::buffer::SharedBuffer;
+use vm::cell::{CellConfig, TapeEdge};
+use vm::tape::Tape;
+use vm::limits::{ExecutionLimits, Limit};
+use vm::snapshot::Snapshot;
 use vm::instruction::Instruction;
 use vm::opcodes::Opcode;
-use debugger::{DebugServer, DebugInformation};
+use vm::error::{ErrorContext, VmError};
+use debugger::{DebugServer, Source};
+
+/// The maximum number of characters read by a single `,` instruction.
+const MAX_READ_SIZE: usize = 64 * 1024;
+
+/// The maximum number of cells `VirtualMachine::write_cell` grows the memory by,
+/// unless a memory limit is set.
+const MAX_WRITE_GROWTH: usize = 1 << 20;
 
 /// The `PrintMode` type.
-enum PrintMode {
+#[derive(Copy, Clone, RustcEncodable, RustcDecodable, PartialEq, Eq, Debug)]
+pub enum PrintMode {
     /// The `.` instruction prints characters
     Char,
 
     /// The `.` instruction prints integers
     Integer,
+
+    /// The `.` instruction prints binary numbers
+    Binary,
+
+    /// The `.` instruction prints hexadecimal numbers
+    Hexadecimal,
+}
+
+/// The `ExecutionStatus` type.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ExecutionStatus {
+    /// There are instructions left to be executed.
+    Running,
+
+    /// The program has finished, either by running out of instructions
+    /// or by being terminated by the `q` instruction.
+    ///
+    /// Contains the exit code of the program.
+    Halted(i32),
 }
 
 /// The `VirtualMachine` type.
@@ -24,28 +58,55 @@ pub struct VirtualMachine {
     ip: usize,
 
     /// The cell pointer
-    cp: usize,
+    cp: i64,
 
     /// The ticks; more specifically the the cycle count
     ticks: u64,
 
-    /// The memory; each item represents a cell.
-    memory: Vec<u32>,
+    /// The memory. See `Tape` for details.
+    tape: Tape,
 
     /// The stack.
-    stack: Vec<u32>,
+    stack: Vec<i64>,
+
+    /// The cell configuration. See `CellConfig` for details.
+    cell_config: CellConfig,
 
     /// The jump table; used for correctly handling nested loops.
     jump_table: BTreeMap<usize, usize>,
 
+    /// A value indicating whether the jump table has been built.
+    prepared: bool,
+
     /// The print mode. See `PrintMode` for details.
     print_mode: PrintMode,
 
+    /// The exit code; set once the program is terminated by `q`.
+    exit_code: Option<i32>,
+
+    /// The execution limits. See `ExecutionLimits` for details.
+    limits: ExecutionLimits,
+
+    /// The time at which the first instruction was executed.
+    started: Option<Instant>,
+
+    /// The tick count, at which `run_for` pauses the execution.
+    deadline: Option<u64>,
+
+    /// The input stream; used by the `,` instruction.
+    input: Box<dyn Read + Send>,
+
+    /// The output stream; used by the `.` instruction.
+    output: Box<dyn Write + Send>,
+
     /// The debug server.
     debug_server: Option<DebugServer>,
 
     /// A value indicating whether a debugger is attached.
     debugger_attached: bool,
+
+    /// The source the instructions were lexed from; shown by the debugger.
+    source: Option<Source>,
 }
 
 /// The `VirtualMachine` implementation.
@@ -63,163 +124,657 @@ impl VirtualMachine {
         // Create the virtual machine
         VirtualMachine {
             ip: 0usize,
-            cp: 0usize,
+            cp: 0i64,
             ticks: 0u64,
             debug_server: None,
             debugger_attached: false,
+            source: None,
             instructions: instructions,
             jump_table: BTreeMap::new(),
+            prepared: false,
             print_mode: PrintMode::Char,
+            exit_code: None,
+            limits: ExecutionLimits::new(),
+            started: None,
+            deadline: None,
+            input: Box::new(io::stdin()),
+            output: Box::new(io::stdout()),
             stack: Vec::with_capacity(stack_size.unwrap_or(DEFAULT_STACK_SIZE)),
-            memory: vec![0; memory_size.unwrap_or(DEFAULT_MEMORY_SIZE)],
+            tape: Tape::new(max(1, memory_size.unwrap_or(DEFAULT_MEMORY_SIZE))),
+            cell_config: CellConfig::new(),
+        }
+    }
+
+    /// Runs the instructions against an in-memory input buffer.
+    ///
+    /// Returns the output produced by the program, along with its exit code or the error,
+    /// which stopped it.
+    pub fn run_buffered(instructions: Vec<Instruction>,
+                        input: &[u8])
+                        -> (Vec<u8>, Result<i32, VmError>) {
+
+        // Create the virtual machine
+        let output = SharedBuffer::new();
+        let mut vm = VirtualMachine::new(instructions, None, None);
+        vm.set_input(Box::new(Cursor::new(input.to_vec())));
+        vm.set_output(Box::new(output.clone()));
+
+        // Run the program and collect the output
+        // The output is kept even if the program fails.
+        let result = vm.run();
+        (output.contents(), result)
+    }
+
+    /// Sets the input stream used by the `,` instruction.
+    ///
+    /// Defaults to the standard input stream.
+    pub fn set_input(&mut self, input: Box<dyn Read + Send>) {
+        self.input = input;
+    }
+
+    /// Sets the output stream used by the `.` instruction.
+    ///
+    /// Defaults to the standard output stream.
+    pub fn set_output(&mut self, output: Box<dyn Write + Send>) {
+        self.output = output;
+    }
+
+    /// Builds the jump table for the instructions.
+    ///
+    /// The jump table maps the position of every jump instruction to its target.
+    /// Any loop start matches any loop end, e.g. `[` may be closed by `)`.
+    pub fn build_jump_table(instructions: &[Instruction])
+                            -> Result<BTreeMap<usize, usize>, VmError> {
+
+        // Creates an `ErrorContext` for the instruction at position `i`
+        let error_context = |i: usize| {
+            ErrorContext {
+                instr: Some(instructions[i].clone()),
+                ip: i,
+                cp: 0,
+                ticks: 0,
+            }
+        };
+        let mut jump_table = BTreeMap::new();
+
+        // Create a loop stack which stores the positions
+        // of the cell and stack loop instructions,
+        // along with the positions of the loop control instructions inside of them
+        let mut loop_stack: Vec<(usize, Vec<usize>)> = Vec::with_capacity(8);
+
+        // Iterate over the instructions
+        for i in 0..instructions.len() {
+
+            // Match the current opcode against the jump operators
+            match instructions[i].opcode {
+
+                // Jump if zero
+                Opcode::JzCell | Opcode::JzStack => loop_stack.push((i, Vec::new())),
+
+                // Jump if not zero
+                Opcode::JnzCell | Opcode::JnzStack => {
+                    if loop_stack.len() == 0 {
+                        return Err(VmError::UnmatchedLoop(error_context(i)));
+                    }
+                    let (start, controls) = loop_stack.pop().unwrap();
+                    jump_table.insert(start, i);
+                    jump_table.insert(i, start);
+
+                    // Resolve the loop control instructions
+                    // A break continues after the end of the loop,
+                    // a stack jump continues at the end of the loop,
+                    // where the loop condition is evaluated again.
+                    for control in controls {
+                        let target = match instructions[control].opcode {
+                            Opcode::Break => i,
+                            _ => i - 1,
+                        };
+                        jump_table.insert(control, target);
+                    }
+                }
+
+                // Loop control
+                Opcode::Break | Opcode::JmpStack => {
+                    match loop_stack.last_mut() {
+                        Some(&mut (_, ref mut controls)) => controls.push(i),
+                        None => {
+                            return Err(VmError::OutsideOfLoop(error_context(i)));
+                        }
+                    }
+                }
+
+                // Ignore the rest
+                _ => (),
+            }
+        }
+
+        // Test if the loop stack still contains any items
+        if loop_stack.len() > 0usize {
+
+            // If so, report the innermost unmatched instruction
+            let start = loop_stack.pop().unwrap().0;
+            return Err(VmError::UnmatchedLoop(error_context(start)));
         }
+        Ok(jump_table)
+    }
+
+    /// Sets a precomputed jump table, which skips building the jump table.
+    ///
+    /// The jump table has to match the loaded instructions.
+    /// See `VirtualMachine::build_jump_table` for details.
+    pub fn set_jump_table(&mut self, jump_table: BTreeMap<usize, usize>) {
+        self.jump_table = jump_table;
+        self.prepared = true;
+    }
+
+    /// Sets the source the instructions were lexed from.
+    ///
+    /// Allows the debugger to show the source around the current instruction.
+    pub fn set_source(&mut self, source: Source) {
+        self.source = Some(source);
+    }
+
+    /// Sets the memory.
+    ///
+    /// Use `Tape::sparse` for programs that move the cell pointer across large distances.
+    pub fn set_tape(&mut self, tape: Tape) {
+        self.tape = tape;
+    }
+
+    /// Sets the cell configuration.
+    ///
+    /// Defaults to `CellConfig::new()`.
+    pub fn set_cell_config(&mut self, cell_config: CellConfig) {
+        self.cell_config = cell_config;
+    }
+
+    /// Sets the execution limits.
+    ///
+    /// Execution stops with `VmError::LimitExceeded` once a limit is exceeded.
+    pub fn set_limits(&mut self, limits: ExecutionLimits) {
+        self.limits = limits;
     }
 
     /// Interprets the loaded instructions.
-    pub fn run(&mut self) {
+    ///
+    /// Returns the exit code of the program.
+    pub fn run(&mut self) -> Result<i32, VmError> {
+
+        // Keep running code until the program is halted
+        loop {
+            match self.step()? {
+                ExecutionStatus::Halted(code) => return Ok(code),
+                ExecutionStatus::Running => (),
+            }
+        }
+    }
+
+    /// Executes a single instruction.
+    ///
+    /// Does nothing if the program has already halted.
+    pub fn step(&mut self) -> Result<ExecutionStatus, VmError> {
 
         // Build the jump table
         // This is important for nested loops to work properly
-        self.build_jump_table();
+        self.prepare()?;
+
+        // Execute the next instruction
+        if self.status() == ExecutionStatus::Running {
+            self.run_cycle()?;
+        }
+        Ok(self.status())
+    }
 
-        // Keep running code until the instruction pointer
-        // is equal to or bigger than the instruction count
-        while self.ip < self.instructions.len() {
+    /// Executes instructions until the tick count has grown by `ticks`.
+    ///
+    /// Scans count one tick per visited cell. A scan, which runs out of ticks,
+    /// stops partway and resumes on the next call, so that the tick count
+    /// never grows by more than `ticks + 1`.
+    /// Stops early if the program halts.
+    pub fn run_for(&mut self, ticks: u64) -> Result<ExecutionStatus, VmError> {
+        self.prepare()?;
+        let deadline = self.ticks.saturating_add(ticks);
+        self.deadline = Some(deadline);
+        let mut result = Ok(());
+        while result.is_ok() && self.ticks < deadline && self.status() == ExecutionStatus::Running {
+            result = self.run_cycle();
+        }
+        self.deadline = None;
+        result.map(|_| self.status())
+    }
+
+    /// Executes instructions until the predicate returns `true`.
+    ///
+    /// The predicate is tested before every instruction.
+    /// Stops early if the program halts.
+    pub fn run_until<F>(&mut self, mut predicate: F) -> Result<ExecutionStatus, VmError>
+        where F: FnMut(&VirtualMachine) -> bool
+    {
+        self.prepare()?;
+        while self.status() == ExecutionStatus::Running && !predicate(self) {
+            self.run_cycle()?;
+        }
+        Ok(self.status())
+    }
 
-            // Execute the next instruction
-            self.run_cycle();
+    /// Gets the execution status.
+    pub fn status(&self) -> ExecutionStatus {
+        match self.exit_code {
+            Some(code) => ExecutionStatus::Halted(code),
+            None if self.ip >= self.instructions.len() => ExecutionStatus::Halted(0),
+            None => ExecutionStatus::Running,
         }
     }
 
+    /// Gets the loaded instructions.
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Gets the jump table. See `VirtualMachine::build_jump_table` for details.
+    ///
+    /// Empty until the first instruction has been executed.
+    pub fn jump_table(&self) -> &BTreeMap<usize, usize> {
+        &self.jump_table
+    }
+
+    /// Gets the instruction to be executed next.
+    ///
+    /// Returns `None` if the program has halted.
+    pub fn current_instruction(&self) -> Option<&Instruction> {
+        match self.status() {
+            ExecutionStatus::Running => self.instructions.get(self.ip),
+            ExecutionStatus::Halted(_) => None,
+        }
+    }
+
+    /// Gets the instruction pointer.
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    /// Gets the cell pointer.
+    pub fn cp(&self) -> i64 {
+        self.cp
+    }
+
+    /// Gets the ticks; more specifically the cycle count.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Gets the memory.
+    pub fn tape(&self) -> &Tape {
+        &self.tape
+    }
+
+    /// Gets the stack; the last item is the top of the stack.
+    pub fn stack(&self) -> &[i64] {
+        &self.stack
+    }
+
+    /// Gets the source the instructions were lexed from, if known.
+    pub fn source(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    /// Gets the cell configuration.
+    pub fn cell_config(&self) -> CellConfig {
+        self.cell_config
+    }
+
+    /// Gets the print mode.
+    pub fn print_mode(&self) -> PrintMode {
+        self.print_mode
+    }
+
+    /// Sets the value of the cell at `index`; used by the debugger.
+    ///
+    /// The value has to fit into the range of a cell.
+    /// Cells outside of the memory can only be set if the memory grows,
+    /// and only as far as the memory limit allows.
+    /// Without a memory limit, the memory grows by at most `MAX_WRITE_GROWTH` cells.
+    pub fn write_cell(&mut self, index: i64, value: i64) -> Result<(), VmError> {
+
+        // Test if the value fits into a cell
+        let width = self.cell_config.width;
+        if value < width.min_value() || value > width.max_value() {
+            return Err(VmError::CellOverflow(self.error_context()));
+        }
+
+        // Test if the memory can hold the cell
+        if !self.tape.contains(index) {
+            if self.cell_config.edge != TapeEdge::Grow {
+                return Err(VmError::MemoryOutOfBounds(self.error_context()));
+            }
+            let max_memory = match self.limits.max_memory {
+                Some(max_memory) => max_memory,
+                None => self.tape.allocated().saturating_add(MAX_WRITE_GROWTH),
+            };
+            let allocated = self.tape.allocated_with(index);
+            if allocated.map_or(true, |allocated| allocated > max_memory) {
+                return Err(VmError::LimitExceeded(self.error_context(), Limit::Memory));
+            }
+        }
+        self.set_cell_at(index, value)
+    }
+
+    /// Sets the value of the stack item at `index`, counted from the bottom of the stack;
+    /// used by the debugger.
+    pub fn write_stack(&mut self, index: usize, value: i64) -> Result<(), VmError> {
+        match self.stack.get_mut(index) {
+            Some(item) => *item = value,
+            None => return Err(VmError::StackUnderflow(self.error_context())),
+        }
+        Ok(())
+    }
+
+    /// Creates an owned copy of the current state.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::new(self)
+    }
+
     /// Interprets the loaded instructions and
     /// runs a debugging server.
     ///
+    /// Waits for a debugger to connect to the bound server first.
     /// Use the `neodbg` executable to connect to the debugger.
-    pub fn run_with_debugger(&mut self) {
+    pub fn run_with_debugger(&mut self, mut server: DebugServer) -> Result<i32, VmError> {
 
-        // Create and bind the debug server
-        let mut server = DebugServer::new();
-        print!("Waiting for debugger... ");
+        // Wait for the debugger
+        match server.address() {
+            Some(address) => print!("Waiting for debugger on {}... ", address),
+            None => print!("Waiting for debugger... "),
+        }
         io::stdout().flush().ok().unwrap();
-        let connected = server.bind_and_accept();
-        self.debug_server = Some(server);
-        self.debugger_attached = connected;
+        let connected = server.accept();
         match connected {
-            true => println!("Connected!"),
-            false => println!("Failed!"),
+            Ok(_) => println!("Connected!"),
+            Err(ref err) => println!("Failed! ({})", err),
         };
+        self.debug_server = Some(server);
+        self.debugger_attached = connected.is_ok();
 
         // Run normally
-        self.run();
+        // The debug server controls the execution from now on.
+        let result = self.run();
 
         // Terminate the debugging session
-        match self.debug_server.as_mut() {
-            Some(server) => {
-                server.update(DebugInformation {
-                    instr: None,
-                    terminate: true,
-                });
-            }
-            None => (),
+        if self.debugger_attached {
+            if let Some(mut server) = self.debug_server.take() {
+                server.terminate(self, &result).ok();
+            }
         }
+        result
     }
 
-    /// Builds the jump table.
-    fn build_jump_table(&mut self) {
+    /// Builds the jump table, unless it has already been built.
+    fn prepare(&mut self) -> Result<(), VmError> {
+        if !self.prepared {
+            self.jump_table = VirtualMachine::build_jump_table(&self.instructions)?;
+            self.prepared = true;
+        }
+        Ok(())
+    }
 
-        // Create a loop stack which stores the positions
-        // of the cell and stack loop instructions
-        let mut loop_stack: Vec<usize> = Vec::with_capacity(8);
-        let mut instr_stack: Vec<Instruction> = Vec::new();
+    /// Creates an `ErrorContext` describing the current state.
+    fn error_context(&self) -> ErrorContext {
+        ErrorContext {
+            instr: self.instructions.get(self.ip).cloned(),
+            ip: self.ip,
+            cp: self.cp,
+            ticks: self.ticks,
+        }
+    }
 
-        // Iterate over the instructions
-        for i in 0..self.instructions.len() {
+    /// Tests if the tick or time limit has been reached.
+    fn check_time_limits(&mut self) -> Result<(), VmError> {
 
-            // Match the current opcode against the conditional jump operators
-            match self.instructions[i].opcode {
+        // Test the tick limit
+        if let Some(max_ticks) = self.limits.max_ticks {
+            if self.ticks >= max_ticks {
+                return Err(VmError::LimitExceeded(self.error_context(), Limit::Ticks));
+            }
+        }
 
-                // Jump if zero
-                Opcode::JzCell | Opcode::JzStack => {
-                    loop_stack.push(i);
-                    instr_stack.push(self.instructions[i].clone());
-                }
+        // Test the time limit
+        // Reading the clock is rather expensive,
+        // which is why it only happens every 1024 ticks.
+        if let Some(max_duration) = self.limits.max_duration {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            if self.ticks % 1024 == 0 && started.elapsed() > max_duration {
+                return Err(VmError::LimitExceeded(self.error_context(), Limit::Duration));
+            }
+        }
+        Ok(())
+    }
 
-                // Jump if not zero
-                Opcode::JnzCell | Opcode::JnzStack => {
-                    let instr = self.instructions[i].clone();
-                    if loop_stack.len() == 0 {
-                        panic!(format!("Unmatched '{}' at position {}", instr.value, instr));
+    /// Tests if the stack can hold `n` more items.
+    fn check_stack_limit(&self, n: usize) -> Result<(), VmError> {
+        match self.limits.max_stack_depth {
+            Some(max_depth) if self.stack.len().saturating_add(n) > max_depth => {
+                Err(VmError::LimitExceeded(self.error_context(), Limit::StackDepth))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fits a value into the range of a cell. See `CellConfig::fit` for details.
+    fn fit(&self, value: i128) -> Result<i64, VmError> {
+        match self.cell_config.fit(value) {
+            Some(value) => Ok(value),
+            None => Err(VmError::CellOverflow(self.error_context())),
+        }
+    }
+
+    /// Moves the cell pointer by `offset` cells.
+    ///
+    /// The behaviour at the edges of the memory depends on the cell configuration.
+    fn move_pointer(&mut self, offset: i64) -> Result<(), VmError> {
+        self.cp = self.address(offset)?;
+        Ok(())
+    }
+
+    /// Gets the index of the cell `offset` cells away from the cell pointer.
+    ///
+    /// The behaviour at the edges of the memory depends on the cell configuration.
+    fn address(&mut self, offset: i64) -> Result<i64, VmError> {
+        let target = match self.cp.checked_add(offset) {
+            Some(target) => target,
+            None => return Err(VmError::MemoryOutOfBounds(self.error_context())),
+        };
+
+        // Test if the target is inside of the memory
+        if self.tape.contains(target) {
+            return Ok(target);
+        }
+
+        // Handle the edge of the memory
+        let (low, high) = (self.tape.low(), self.tape.high());
+        match self.cell_config.edge {
+            TapeEdge::Error => Err(VmError::MemoryOutOfBounds(self.error_context())),
+            TapeEdge::Clamp => Ok(if target < low { low } else { high - 1 }),
+            TapeEdge::Wrap => {
+                let len = high - low;
+                Ok(low + (((target - low) % len) + len) % len)
+            }
+            TapeEdge::Grow => {
+                if let Some(max_memory) = self.limits.max_memory {
+                    let allocated = self.tape.allocated_with(target);
+                    if allocated.map_or(true, |allocated| allocated > max_memory) {
+                        return Err(VmError::LimitExceeded(self.error_context(), Limit::Memory));
                     }
-                    instr_stack.pop();
-                    let start = loop_stack.pop().unwrap();
-                    self.jump_table.insert(start, i);
-                    self.jump_table.insert(i, start);
                 }
+                match self.tape.grow(target) {
+                    true => Ok(target),
+                    false => Err(VmError::MemoryOutOfBounds(self.error_context())),
+                }
+            }
+        }
+    }
 
-                // Ignore the rest
-                _ => (),
+    /// Sets the value of the cell.
+    fn set_cell(&mut self, value: i64) -> Result<(), VmError> {
+        let cp = self.cp;
+        self.set_cell_at(cp, value)
+    }
+
+    /// Sets the value of the cell at `index`.
+    fn set_cell_at(&mut self, index: i64, value: i64) -> Result<(), VmError> {
+        if !self.tape.set(index, value) {
+            return Err(VmError::MemoryOutOfBounds(self.error_context()));
+        }
+
+        // Test the memory limit
+        // Writing to a sparse tape may allocate a cell.
+        if let Some(max_memory) = self.limits.max_memory {
+            if self.tape.allocated() > max_memory {
+                return Err(VmError::LimitExceeded(self.error_context(), Limit::Memory));
             }
         }
+        Ok(())
+    }
 
-        // Test if the loop stack still contains any items
-        if loop_stack.len() > 0usize {
+    /// Formats a value according to the print mode. See `PrintMode` for details.
+    fn format(&self, value: i64) -> Result<String, VmError> {
+        match self.print_mode {
+            PrintMode::Char => {
+                match char::from_u32(value as u32) {
+                    Some(chr) if value >= 0 && value <= u32::max_value() as i64 => {
+                        Ok(chr.to_string())
+                    }
+                    _ => Err(VmError::InvalidCharacter(self.error_context(), value)),
+                }
+            }
+            PrintMode::Integer => Ok(format!("{}", value)),
+            PrintMode::Binary => Ok(format!("{:b}", value)),
+            PrintMode::Hexadecimal => Ok(format!("{:x}", value)),
+        }
+    }
 
-            // If so, panic!
-            let instr = instr_stack.pop().unwrap();
-            panic!(format!("Unmatched '{}' at position {}", instr.value, instr));
+    /// Writes text to the output stream.
+    fn print(&mut self, text: String) -> Result<(), VmError> {
+        match self.output.write_all(text.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(VmError::Io(self.error_context(), err)),
         }
     }
 
     /// Executes a single instruction.
-    fn run_cycle(&mut self) {
+    fn run_cycle(&mut self) -> Result<(), VmError> {
 
-        // Fetch the instruction
-        let instr = &self.instructions[self.ip];
+        // Test the execution limits
+        self.check_time_limits()?;
 
         // Test if a debugger is attached
-        if self.debugger_attached && self.debug_server.is_some() {
-            let mut server = self.debug_server.as_mut().unwrap();
-            if !server.update(DebugInformation {
-                instr: Some(instr.clone()),
-                terminate: false,
-            }) {
-                println!("***\nWARN: Debugger disconnected!\n***");
-                self.debugger_attached = false;
+        // The debug server is taken out of the virtual machine,
+        // so that it can inspect the state while controlling the execution.
+        if self.debugger_attached {
+            if let Some(mut server) = self.debug_server.take() {
+                if let Err(err) = server.debug(self) {
+                    println!("***\nWARN: Debugger disconnected ({})!\n***", err);
+                    self.debugger_attached = false;
+                }
+                self.debug_server = Some(server);
             }
         }
 
+        // Fetch the instruction
+        let instr = &self.instructions[self.ip];
+
         match instr.opcode {
 
             // Increment the cell pointer
-            Opcode::IncPtr => self.cp += instr.argument.unwrap_or(1) as usize,
+            Opcode::IncPtr => self.move_pointer(instr.argument.unwrap_or(1) as i64)?,
 
             // Decrement the cell pointer
-            Opcode::DecPtr => {
-                self.cp = max(0, self.cp - max(0, instr.argument.unwrap_or(1)) as usize)
-            }
+            Opcode::DecPtr => self.move_pointer(-(instr.argument.unwrap_or(1) as i64))?,
 
             // Clear the cell value
-            Opcode::Clear => self.memory[self.cp] = 0,
+            Opcode::Clear => {
+                let index = self.address(instr.offset as i64)?;
+                self.set_cell_at(index, 0)?;
+            }
+
+            // Set the cell value
+            Opcode::Set => {
+                let value = instr.argument.unwrap_or(0) as i128;
+                let index = self.address(instr.offset as i64)?;
+                self.set_cell_at(index, self.fit(value)?)?;
+            }
+
+            // Move the cell pointer in steps of `argument` cells until it reaches a zero cell
+            // Every visited cell counts as one tick, so that the execution limits still apply.
+            // Once the ticks of `run_for` are used up, the scan stops without advancing
+            // the instruction pointer, so that it resumes from the current cell.
+            Opcode::ScanLeft | Opcode::ScanRight => {
+                let stride = match instr.opcode {
+                    Opcode::ScanRight => instr.argument.unwrap_or(1) as i64,
+                    _ => -(instr.argument.unwrap_or(1) as i64),
+                };
+                while self.tape.get(self.cp) != 0 {
+                    if self.deadline.map_or(false, |deadline| self.ticks >= deadline) {
+                        return Ok(());
+                    }
+                    self.move_pointer(stride)?;
+                    self.ticks += 1;
+                    self.check_time_limits()?;
+                }
+            }
+
+            // Add or subtract a multiple of the cell value to or from another cell
+            // Nothing happens if the cell is zero, just like the loop this replaces,
+            // which would not have moved the cell pointer.
+            Opcode::MulAdd | Opcode::MulSub => {
+                let multiplier = self.tape.get(self.cp) as i128;
+                if multiplier != 0 {
+                    let factor = match instr.opcode {
+                        Opcode::MulAdd => instr.argument.unwrap_or(1) as i128,
+                        _ => -(instr.argument.unwrap_or(1) as i128),
+                    };
+                    let index = self.address(instr.offset as i64)?;
+                    let value = self.tape.get(index) as i128 + multiplier * factor;
+                    self.set_cell_at(index, self.fit(value)?)?;
+                }
+            }
 
             // Increment the cell value
-            Opcode::Inc => self.memory[self.cp] += instr.argument.unwrap_or(1),
+            Opcode::Inc => {
+                let arg = instr.argument.unwrap_or(1) as i128;
+                let index = self.address(instr.offset as i64)?;
+                let value = self.tape.get(index) as i128 + arg;
+                self.set_cell_at(index, self.fit(value)?)?;
+            }
 
             // Decrement the cell value
             Opcode::Dec => {
-                self.memory[self.cp] = max(0, self.memory[self.cp] - instr.argument.unwrap_or(1))
+                let arg = instr.argument.unwrap_or(1) as i128;
+                let index = self.address(instr.offset as i64)?;
+                let value = self.tape.get(index) as i128 - arg;
+                self.set_cell_at(index, self.fit(value)?)?;
             }
 
             // Double the cell value
-            Opcode::Double => self.memory[self.cp] *= 2,
+            Opcode::Double => {
+                let value = self.tape.get(self.cp) as i128 * 2;
+                self.set_cell(self.fit(value)?)?;
+            }
 
             // Halve the cell value
-            Opcode::Halve => self.memory[self.cp] /= 2,
+            Opcode::Halve => {
+                let value = self.tape.get(self.cp) / 2;
+                self.set_cell(value)?;
+            }
 
             // Push the cell value onto the stack
-            Opcode::Push => self.stack.push(self.memory[self.cp]),
+            Opcode::Push => {
+                self.check_stack_limit(1)?;
+                let index = self.address(instr.offset as i64)?;
+                self.stack.push(self.tape.get(index));
+            }
 
             // Set the value of the cell to the number of items on the stack
-            Opcode::Count => self.memory[self.cp] = self.stack.len() as u32,
+            Opcode::Count => self.set_cell(self.fit(self.stack.len() as i128)?)?,
 
             // Set the printing mode to `Char`, see `PrintMode` for details.
             Opcode::ChrMod => self.print_mode = PrintMode::Char,
@@ -227,35 +782,83 @@ impl VirtualMachine {
             // Set the printing mode to `Integer`, see `PrintMode` for details.
             Opcode::IntMod => self.print_mode = PrintMode::Integer,
 
+            // Set the printing mode to `Binary`, see `PrintMode` for details.
+            Opcode::BinMod => self.print_mode = PrintMode::Binary,
+
+            // Set the printing mode to `Hexadecimal`, see `PrintMode` for details.
+            Opcode::HexMod => self.print_mode = PrintMode::Hexadecimal,
+
+            // Terminate the program
+            // The value of the cell is used as the exit code.
+            Opcode::Terminate => self.exit_code = Some(self.tape.get(self.cp) as i32),
+
             // Print the value of the current cell
             // The output format depends on the printing mode, see `PrintMode` for details.
             Opcode::Print => {
-                match self.print_mode {
-                    PrintMode::Char => print!("{}", char::from_u32(self.memory[self.cp]).unwrap()),
-                    PrintMode::Integer => print!("{}", self.memory[self.cp]),
+                let index = self.address(instr.offset as i64)?;
+                let value = self.tape.get(index);
+                let text = self.format(value)?;
+                self.print(text)?;
+            }
+
+            // Print a string, as if it was pushed, popped and printed character by character
+            // The cell ends up holding the zero value that terminates the string.
+            Opcode::PrintStr => {
+                self.check_stack_limit(instr.value.chars().count() + 1)?;
+                let mut text = String::with_capacity(instr.value.len());
+                let mut error = None;
+                for chr in instr.value.chars() {
+                    match self.fit(chr as i128).and_then(|value| self.format(value)) {
+                        Ok(value) => text.push_str(&value),
+                        Err(err) => {
+                            error = Some(err);
+                            break;
+                        }
+                    }
+                }
+
+                // Print the characters up to the first invalid one
+                self.print(text)?;
+                if let Some(err) = error {
+                    return Err(err);
                 }
+                self.set_cell(0)?;
             }
 
-            // Read n character from the standard input stream
+            // Read n character from the input stream
             // The first character is assigned to the current cell
             // The other characters are pushed onto the stack in reverse order
             Opcode::Read => {
-                // Flush stdout before reading
+                // Flush the output stream before reading
                 // This is needed for a potential prompt to be printed before reading
-                io::stdout().flush().ok().unwrap();
+                if let Err(err) = self.output.flush() {
+                    return Err(VmError::Io(self.error_context(), err));
+                }
 
                 // Read n or 512 characters
-                let n = match self.stack.pop() {
-                    Some(0) | None => 512,
-                    Some(n) => n,
+                // The characters are pushed onto the stack along with a zero value,
+                // replacing n, which is why the stack limit is tested up front.
+                // A single read returns at most `MAX_READ_SIZE` characters.
+                let n = match self.stack.last() {
+                    Some(&n) if n > 0 => n as usize,
+                    _ => 512,
+                };
+                if n != 1 {
+                    let popped = min(self.stack.len(), 1);
+                    self.check_stack_limit(n.saturating_add(1) - popped)?;
+                }
+                self.stack.pop();
+                let mut buf = vec![0u8; min(n, MAX_READ_SIZE)];
+                let size = match self.input.read(buf.as_mut_slice()) {
+                    Ok(size) => size,
+                    Err(err) => return Err(VmError::Io(self.error_context(), err)),
                 };
-                let mut buf = vec![0u8; n as usize];
-                io::stdin().read(buf.as_mut_slice()).ok().unwrap();
+                buf.truncate(size);
 
                 // Assign the character to the cell,
                 // if only one character was read
                 if n == 1 {
-                    self.memory[self.cp] = buf[0] as u32;
+                    self.set_cell(buf.first().cloned().unwrap_or(0) as i64)?;
                 }
                 // Else, push all characters onto the stack
                 // just like a normal qo string
@@ -264,7 +867,7 @@ impl VirtualMachine {
                     let mut i = buf.len();
                     while i > 0 {
                         if buf[i - 1] != 0 {
-                            self.stack.push(buf[i - 1] as u32);
+                            self.stack.push(buf[i - 1] as i64);
                         }
                         i -= 1;
                     }
@@ -274,57 +877,51 @@ impl VirtualMachine {
             // Push a string onto the stack, followed by a zero value
             // The string is pushed in reverse order, that way it can be easily processed.
             Opcode::Str => {
+                self.check_stack_limit(instr.value.chars().count() + 1)?;
                 self.stack.push(0);
-                let mut vec: Vec<char> = instr.value.chars().collect();
-                let mut i = vec.len() - 1;
-                loop {
-                    if i > 0 && vec[i - 1] == '\\' {
-                        let chr = match vec[i] {
-                            '0' => '\0',
-                            'n' => '\n',
-                            'r' => '\r',
-                            't' => '\t',
-                            '\\' => '\\',
-                            _ => panic!(format!("Invalid escape sequence: \\{}", vec[i])),
-                        };
-                        if chr != '_' {
-                            i -= 1;
-                            vec.remove(i);
-                            vec.remove(i);
-                            vec.insert(i, chr);
-                        }
-                    }
-                    self.stack.push(vec[i] as u32);
-                    if i == 0 {
-                        break;
-                    }
-                    i -= 1;
+                for chr in instr.value.chars().rev() {
+                    self.stack.push(chr as i64);
                 }
             }
 
             // Jump to the end of the loop if the value of the cell is zero
             Opcode::JzCell => {
-                if self.memory[self.cp] == 0 {
+                if self.tape.get(self.cp) == 0 {
                     self.ip = self.jump_table[&self.ip];
                 }
             }
 
             // Jump to the beginning of the loop if the value of the cell is not zero
             Opcode::JnzCell => {
-                if self.memory[self.cp] != 0 {
+                if self.tape.get(self.cp) != 0 {
                     self.ip = self.jump_table[&self.ip];
                 }
             }
 
             // Pop the top value off the stack and assign it to the cell
-            Opcode::Pop => self.memory[self.cp] = self.stack.pop().unwrap_or(0),
+            Opcode::Pop => {
+                let index = self.address(instr.offset as i64)?;
+                let value = self.stack.pop().unwrap_or(0) as i128;
+                self.set_cell_at(index, self.fit(value)?)?;
+            }
+
+            // Assign the top value on the stack to the cell, without popping it
+            Opcode::Peek => {
+                let index = self.address(instr.offset as i64)?;
+                let value = match self.stack.last() {
+                    Some(&value) => value as i128,
+                    None => return Err(VmError::StackUnderflow(self.error_context())),
+                };
+                self.set_cell_at(index, self.fit(value)?)?;
+            }
 
             // Duplicate the top value on the stack
             Opcode::Dup => {
                 let stack_length = self.stack.len();
                 if stack_length == 0 {
-                    panic!(format!("Attempt to pop value off empty stack (at {})", instr));
+                    return Err(VmError::StackUnderflow(self.error_context()));
                 }
+                self.check_stack_limit(1)?;
                 let value = self.stack.pop().unwrap();
                 self.stack.push(value);
                 self.stack.push(value);
@@ -335,7 +932,7 @@ impl VirtualMachine {
             Opcode::Swap => {
                 let stack_length = self.stack.len();
                 if stack_length < 2 {
-                    panic!(format!("Attempt to pop value off empty stack (at {})", instr));
+                    return Err(VmError::StackUnderflow(self.error_context()));
                 }
                 let fst = self.stack.pop().unwrap();
                 let snd = self.stack.pop().unwrap();
@@ -348,11 +945,11 @@ impl VirtualMachine {
             Opcode::Compare => {
                 let stack_length = self.stack.len();
                 if stack_length < 2 {
-                    panic!(format!("Attempt to pop value off empty stack (at {})", instr));
+                    return Err(VmError::StackUnderflow(self.error_context()));
                 }
                 let fst = self.stack[stack_length - 1];
                 let snd = self.stack[stack_length - 2];
-                self.memory[self.cp] = (fst == snd) as u32;
+                self.set_cell((fst == snd) as i64)?;
             }
 
             // Jump to the end of the loop if the top value on the stack is zero
@@ -376,12 +973,23 @@ impl VirtualMachine {
                 }
             }
 
-            // Panic if an unknown instruction is encountered
-            _ => panic!(format!("Unimplemented instruction '{}' (at {})", instr.value, instr)),
+            // Pop the top value off the stack and jump to the end of the loop if it is not zero
+            // The loop condition is evaluated again, just like at the end of an iteration.
+            Opcode::JmpStack => {
+                match self.stack.pop() {
+                    Some(0) => (),
+                    Some(_) => self.ip = self.jump_table[&self.ip],
+                    None => return Err(VmError::StackUnderflow(self.error_context())),
+                }
+            }
+
+            // Jump behind the end of the loop
+            Opcode::Break => self.ip = self.jump_table[&self.ip],
         }
 
         // Increment the instruction pointer and the tick value
         self.ip += 1;
         self.ticks += 1;
+        Ok(())
     }
 }
@@ -0,0 +1,70 @@
+extern crate neoqo;
+mod common;
+use neoqo::{VirtualMachine, SharedBuffer, ExecutionStatus};
+use neoqo::{Breakpoint, BreakpointKind, Condition, Operand, Comparison};
+use common::lex;
+
+/// Runs the source and gets the instruction pointers, at which the breakpoint is hit.
+///
+/// The breakpoint observes every instruction, just like it does in the debug server.
+fn hits(source: &str, breakpoint: &Breakpoint) -> Vec<usize> {
+    let mut vm = VirtualMachine::new(lex(source), None, None);
+    vm.set_output(Box::new(SharedBuffer::new()));
+    let mut previous = breakpoint.observe(&vm);
+    let mut hits = Vec::new();
+    while vm.step().unwrap() == ExecutionStatus::Running {
+        let current = breakpoint.observe(&vm);
+        if breakpoint.is_hit(&vm, previous, current) {
+            hits.push(vm.ip());
+        }
+        previous = current;
+    }
+    hits
+}
+
+#[test]
+fn line_breakpoints_are_hit_when_the_line_is_entered() {
+    let breakpoint = Breakpoint::new(BreakpointKind::Line(1, None));
+    assert_eq!(hits("+\n++\n+\n+", &breakpoint), vec![1]);
+    assert_eq!(hits("+\n+", &Breakpoint::new(BreakpointKind::Line(2, None))), vec![]);
+}
+
+#[test]
+fn line_breakpoints_are_hit_again_when_a_loop_jumps_back() {
+    // 0 + 1 + 2 + 3 [ 4 - 5 ]
+    let breakpoint = Breakpoint::new(BreakpointKind::Line(1, None));
+    assert_eq!(hits("+++\n[-]", &breakpoint), vec![3, 4, 4]);
+    let breakpoint = Breakpoint::new(BreakpointKind::Line(1, Some(1)));
+    assert_eq!(hits("+++\n[-]", &breakpoint), vec![4, 4, 4]);
+}
+
+#[test]
+fn tick_breakpoints_are_hit_once() {
+    let breakpoint = Breakpoint::new(BreakpointKind::Ticks(2));
+    assert_eq!(hits("+++[-]", &breakpoint), vec![2]);
+}
+
+#[test]
+fn watchpoints_are_hit_when_the_value_changes() {
+    assert_eq!(hits("+>+<+i.", &Breakpoint::new(BreakpointKind::WatchCell(0))), vec![1, 5]);
+    assert_eq!(hits("::;&+", &Breakpoint::new(BreakpointKind::WatchStack)), vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn conditions_restrict_when_breakpoints_are_hit() {
+    let breakpoint = Breakpoint {
+        kind: BreakpointKind::WatchCell(0),
+        condition: Condition::parse("cell == 2"),
+    };
+    assert_eq!(hits("+++[-]", &breakpoint), vec![2, 5]);
+
+    assert_eq!(Condition::parse("cell[-3] >= 4"),
+               Some(Condition {
+                   operand: Operand::CellAt(-3),
+                   comparison: Comparison::GreaterOrEqual,
+                   value: 4,
+               }));
+    for &text in &["cell", "cell = 1", "cell[x] == 1", "depth == x", "top == 1 2"] {
+        assert_eq!(Condition::parse(text), None, "{}", text);
+    }
+}
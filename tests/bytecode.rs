@@ -0,0 +1,135 @@
+extern crate neoqo;
+mod common;
+use std::io::Cursor;
+use neoqo::{Optimizer, OptimizerPass, Bytecode, BytecodeError};
+use neoqo::{CellConfig, TapeEdge};
+use common::lex;
+
+/// Writes the bytecode and reads it back.
+fn reread(bytecode: &Bytecode) -> Result<Bytecode, BytecodeError> {
+    let mut data = Vec::new();
+    bytecode.write(&mut data).unwrap();
+    Bytecode::read(&mut Cursor::new(data))
+}
+
+/// Tests if reading the bytecode fails because of its contents.
+fn is_rejected(bytecode: &Bytecode) -> bool {
+    match reread(bytecode) {
+        Err(BytecodeError::Decoding(_)) => true,
+        _ => false,
+    }
+}
+
+#[test]
+fn bytecode_survives_a_round_trip() {
+    let mut optimizer = Optimizer::new(lex("+++[>++<-]>i.\"ab\"(;.);[!]"), None);
+    optimizer.optimize();
+    for &debug_info in &[true, false] {
+        let bytecode = Bytecode::new(optimizer.instructions.clone(),
+                                     optimizer.passes().to_vec(),
+                                     CellConfig::new(),
+                                     debug_info)
+            .unwrap();
+        let mut data = Vec::new();
+        bytecode.write(&mut data).unwrap();
+        assert!(Bytecode::is_bytecode(&data));
+
+        let read = Bytecode::read(&mut Cursor::new(data)).unwrap();
+        assert_eq!(read, bytecode);
+        if debug_info {
+            assert_eq!(read.to_instructions(), optimizer.instructions);
+        }
+    }
+}
+
+#[test]
+fn bytecode_remembers_the_cell_configuration() {
+    let passes = OptimizerPass::all().to_vec();
+    let mut optimizer = Optimizer::new(lex("+++[->+<]>>+<<i."), Some(passes.clone()));
+    optimizer.optimize();
+    let bytecode = Bytecode::new(optimizer.instructions.clone(),
+                                 passes.clone(),
+                                 CellConfig::new(),
+                                 true)
+        .unwrap();
+    let read = reread(&bytecode).unwrap();
+    assert_eq!(read.cell_config, CellConfig::new());
+    assert!(read.supports(&CellConfig::new()));
+    for &edge in &[TapeEdge::Clamp, TapeEdge::Wrap] {
+        assert!(!read.supports(&CellConfig { edge: edge, ..CellConfig::new() }));
+    }
+
+    // Passes skipped by the optimizer are left out
+    let clamped = CellConfig { edge: TapeEdge::Clamp, ..CellConfig::new() };
+    let bytecode = Bytecode::new(lex("+"), passes, clamped, true).unwrap();
+    assert!(!bytecode.passes.contains(&OptimizerPass::OptimizeOffsets));
+    assert!(reread(&bytecode).unwrap().supports(&clamped));
+}
+
+#[test]
+fn bytecode_accepts_loops_of_mixed_kinds() {
+    for &source in &["+[-)i.", "\"ab\"(;]i.", "+[(-!])"] {
+        let bytecode = Bytecode::new(lex(source), Vec::new(), CellConfig::new(), true).unwrap();
+        assert_eq!(reread(&bytecode).unwrap(), bytecode, "{}", source);
+    }
+}
+
+#[test]
+fn bytecode_rejects_foreign_data() {
+    match Bytecode::read(&mut Cursor::new(b"QOX\0\0\0".to_vec())) {
+        Err(BytecodeError::InvalidMagic) => (),
+        other => panic!("{:?}", other),
+    }
+    match Bytecode::read(&mut Cursor::new(b"QOC\0\xff\xff".to_vec())) {
+        Err(BytecodeError::UnsupportedVersion(0xffff)) => (),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn bytecode_rejects_invalid_jump_tables() {
+    // 0 + 1 [ 2 > 3 + 4 [ 5 - 6 ] 7 < 8 - 9 ]
+    let bytecode = Bytecode::new(lex("+[>+[-]<-]"), Vec::new(), CellConfig::new(), true).unwrap();
+    assert!(reread(&bytecode).is_ok());
+
+    // Targets out of range, of the wrong kind or missing
+    for &(from, to) in &[(1, 100), (1, 5), (9, 4)] {
+        let mut invalid = bytecode.clone();
+        invalid.jump_table.insert(from, to);
+        assert!(is_rejected(&invalid), "{} -> {}", from, to);
+    }
+    let mut invalid = bytecode.clone();
+    invalid.jump_table.remove(&9);
+    assert!(is_rejected(&invalid));
+
+    // Loops, which do not point back at each other
+    let mut invalid = bytecode.clone();
+    invalid.jump_table.insert(1, 6);
+    invalid.jump_table.insert(6, 1);
+    assert!(is_rejected(&invalid));
+
+    // A break outside of the loop it jumps to
+    // 0 + 1 [ 2 [ 3 - 4 ] 5 ! 6 - 7 ]
+    let bytecode = Bytecode::new(lex("+[[-]!-]"), Vec::new(), CellConfig::new(), true).unwrap();
+    assert!(reread(&bytecode).is_ok());
+    let mut invalid = bytecode.clone();
+    invalid.jump_table.insert(5, 4);
+    assert!(is_rejected(&invalid));
+}
+
+#[test]
+fn bytecode_rejects_oversized_data() {
+    let bytecode = Bytecode::new(lex("\"zzzzzzzz\""), Vec::new(), CellConfig::new(), true).unwrap();
+    let mut data = Vec::new();
+    bytecode.write(&mut data).unwrap();
+
+    // Claim the string takes up most of the address space
+    let index = data.windows(8).position(|window| window == b"zzzzzzzz").unwrap();
+    for byte in &mut data[index - 8..index - 1] {
+        *byte = 0xff;
+    }
+    match Bytecode::read(&mut Cursor::new(data)) {
+        Err(BytecodeError::Decoding(_)) => (),
+        other => panic!("{:?}", other),
+    }
+}
@@ -0,0 +1,68 @@
+extern crate neoqo;
+use neoqo::{CellConfig, CellWidth, Overflow};
+
+/// The cell widths along with the biggest value their cells can hold.
+const WIDTHS: [(CellWidth, i128); 3] = [(CellWidth::U8, 0xff),
+                                        (CellWidth::U16, 0xffff),
+                                        (CellWidth::U32, 0xffff_ffff)];
+
+/// Creates a cell configuration using the width and the overflow behaviour.
+fn config(width: CellWidth, overflow: Overflow) -> CellConfig {
+    CellConfig {
+        width: width,
+        overflow: overflow,
+        ..CellConfig::new()
+    }
+}
+
+#[test]
+fn values_in_range_are_kept() {
+    for &(width, max) in &WIDTHS {
+        for &overflow in &[Overflow::Wrap, Overflow::Saturate, Overflow::Error] {
+            let config = config(width, overflow);
+            assert_eq!(config.fit(0), Some(0));
+            assert_eq!(config.fit(max), Some(max as i64));
+        }
+    }
+    let config = config(CellWidth::I64, Overflow::Error);
+    assert_eq!(config.fit(i64::min_value() as i128), Some(i64::min_value()));
+    assert_eq!(config.fit(i64::max_value() as i128), Some(i64::max_value()));
+}
+
+#[test]
+fn wrapping_cells_wrap_around() {
+    for &(width, max) in &WIDTHS {
+        let config = config(width, Overflow::Wrap);
+        assert_eq!(config.fit(max + 1), Some(0), "{:?}", width);
+        assert_eq!(config.fit(-1), Some(max as i64), "{:?}", width);
+        assert_eq!(config.fit(3 * (max + 1) + 5), Some(5), "{:?}", width);
+        assert_eq!(config.fit(-2 * (max + 1) - 5), Some(max as i64 - 4), "{:?}", width);
+    }
+    let config = config(CellWidth::I64, Overflow::Wrap);
+    assert_eq!(config.fit(i64::max_value() as i128 + 1), Some(i64::min_value()));
+    assert_eq!(config.fit(i64::min_value() as i128 - 1), Some(i64::max_value()));
+}
+
+#[test]
+fn saturating_cells_stop_at_the_bounds() {
+    for &(width, max) in &WIDTHS {
+        let config = config(width, Overflow::Saturate);
+        assert_eq!(config.fit(max + 1), Some(max as i64), "{:?}", width);
+        assert_eq!(config.fit(-1), Some(0), "{:?}", width);
+    }
+    let config = config(CellWidth::I64, Overflow::Saturate);
+    assert_eq!(config.fit(i64::max_value() as i128 + 1), Some(i64::max_value()));
+    assert_eq!(config.fit(i64::min_value() as i128 - 1), Some(i64::min_value()));
+}
+
+#[test]
+fn failing_cells_reject_values_out_of_range() {
+    for &(width, max) in &WIDTHS {
+        let config = config(width, Overflow::Error);
+        assert_eq!(config.fit(max + 1), None, "{:?}", width);
+        assert_eq!(config.fit(-1), None, "{:?}", width);
+    }
+    let config = config(CellWidth::I64, Overflow::Error);
+    assert_eq!(config.fit(i64::max_value() as i128 + 1), None);
+    assert_eq!(config.fit(i64::min_value() as i128 - 1), None);
+}
@@ -0,0 +1,16 @@
+#![allow(dead_code)]
+use neoqo::{Lexer, Instruction};
+
+/// Tokenizes the source, keeping the diagnostics.
+pub fn tokenize(source: &str) -> Lexer {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.tokenize();
+    lexer
+}
+
+/// Tokenizes the source, which must not contain any errors.
+pub fn lex(source: &str) -> Vec<Instruction> {
+    let lexer = tokenize(source);
+    assert!(!lexer.has_errors(), "{:?}", lexer.diagnostics);
+    lexer.tokens
+}
@@ -0,0 +1,166 @@
+#![cfg(unix)]
+extern crate neoqo;
+mod common;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::Duration;
+use neoqo::{DebugServer, DebugClient, DebugAddress, DebugCommand, DebugResponse, DebugError};
+use neoqo::{VirtualMachine, SharedBuffer, StopReason, PROTOCOL_VERSION, MAX_FRAME_LENGTH};
+use common::lex;
+
+/// Gets the path of a socket file, which is unique to the test.
+fn socket_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("neoqo-{}-{}.sock", process::id(), name));
+    fs::remove_file(&path).ok();
+    path
+}
+
+/// Gets the path of the lock file guarding the socket file.
+fn lock_path(path: &PathBuf) -> PathBuf {
+    PathBuf::from(format!("{}.lock", path.display()))
+}
+
+/// Connects a client to a listener, which sends the frame and closes the connection.
+fn receive_frame(name: &str,
+                 length: u32,
+                 version: u16,
+                 data: &[u8])
+                 -> Result<DebugResponse, DebugError> {
+    let path = socket_path(name);
+    let listener = UnixListener::bind(&path).unwrap();
+    let mut frame = Vec::new();
+    frame.extend_from_slice(&length.to_be_bytes());
+    frame.extend_from_slice(&version.to_be_bytes());
+    frame.extend_from_slice(data);
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(&frame).unwrap();
+    });
+    let mut client = DebugClient::new();
+    client.connect(&DebugAddress::Unix(path.clone()), Duration::from_secs(5)).unwrap();
+    server.join().unwrap();
+    fs::remove_file(&path).ok();
+    client.receive()
+}
+
+#[test]
+fn unix_sockets_stay_bound_while_the_server_is_live() {
+    let path = socket_path("live");
+    let address = DebugAddress::Unix(path.clone());
+    let mut server = DebugServer::bind(&address).unwrap();
+
+    // A second server is refused without disturbing the first one
+    assert!(DebugServer::bind(&address).is_err());
+    assert!(path.exists());
+
+    // The first server still accepts its client
+    let client = thread::spawn(move || {
+        let mut client = DebugClient::new();
+        client.connect(&address, Duration::from_secs(5)).is_ok()
+    });
+    server.accept().unwrap();
+    assert!(client.join().unwrap());
+
+    // Accepting frees the address
+    assert!(!path.exists());
+    assert!(!lock_path(&path).exists());
+}
+
+#[test]
+fn unix_sockets_replace_files_left_behind() {
+    let path = socket_path("stale");
+    let address = DebugAddress::Unix(path.clone());
+
+    // A socket file and a lock file of a process, which is no longer running
+    drop(UnixListener::bind(&path).unwrap());
+    fs::write(lock_path(&path), i32::max_value().to_string()).unwrap();
+    let server = DebugServer::bind(&address).unwrap();
+    assert_eq!(fs::read_to_string(lock_path(&path)).unwrap(),
+               process::id().to_string());
+    drop(server);
+    assert!(!path.exists());
+
+    // Other files are left untouched
+    fs::write(&path, "data").unwrap();
+    assert!(DebugServer::bind(&address).is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "data");
+    assert!(!lock_path(&path).exists());
+    fs::remove_file(&path).ok();
+}
+
+#[test]
+fn commands_and_responses_survive_a_round_trip() {
+    let path = socket_path("session");
+    let address = DebugAddress::Unix(path.clone());
+    let server = DebugServer::bind(&address).unwrap();
+    let output = SharedBuffer::new();
+    let mut vm = VirtualMachine::new(lex("+++i."), None, None);
+    vm.set_output(Box::new(output.clone()));
+    let program = thread::spawn(move || vm.run_with_debugger(server).ok());
+
+    // Stop at the first instruction, modify a cell and run to the end
+    let mut client = DebugClient::new();
+    client.connect(&address, Duration::from_secs(5)).unwrap();
+    match client.receive().unwrap() {
+        DebugResponse::Stopped(ref info) if info.reason == StopReason::Entry => (),
+        response => panic!("Unexpected response {:?}", response),
+    }
+    client.send(&DebugCommand::SetCell(0, 39)).unwrap();
+    assert_eq!(client.receive().unwrap(), DebugResponse::Modified);
+    client.send(&DebugCommand::Continue).unwrap();
+    assert_eq!(client.receive().unwrap(), DebugResponse::Running);
+    match client.receive().unwrap() {
+        DebugResponse::Stopped(ref info) if info.reason == StopReason::Halted(0) => (),
+        response => panic!("Unexpected response {:?}", response),
+    }
+    assert_eq!(program.join().unwrap(), Some(0));
+    assert_eq!(output.contents(), b"42");
+}
+
+#[test]
+fn frames_of_other_protocol_versions_are_rejected() {
+    match receive_frame("version", 5, PROTOCOL_VERSION + 1, b"abc") {
+        Err(DebugError::UnsupportedVersion(version)) => {
+            assert_eq!(version, PROTOCOL_VERSION + 1)
+        }
+        result => panic!("Unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn oversized_frames_are_rejected_before_reading_them() {
+    // The connection is closed right after the header,
+    // so reading the frame would fail with an I/O error instead
+    match receive_frame("oversized", MAX_FRAME_LENGTH + 1, PROTOCOL_VERSION, b"") {
+        Err(DebugError::Decoding(_)) => (),
+        result => panic!("Unexpected result {:?}", result),
+    }
+    match receive_frame("truncated", MAX_FRAME_LENGTH, PROTOCOL_VERSION, b"") {
+        Err(DebugError::Io(_)) | Err(DebugError::Disconnected) => (),
+        result => panic!("Unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn addresses_are_parsed_by_their_form() {
+    let tcp = |address: &str| DebugAddress::Tcp(address.to_string());
+    let unix = |path: &str| DebugAddress::Unix(PathBuf::from(path));
+    assert_eq!(DebugAddress::parse("38101"), tcp("127.0.0.1:38101"));
+    assert_eq!(DebugAddress::parse("localhost:38101"), tcp("localhost:38101"));
+    assert_eq!(DebugAddress::parse("[::1]:38101"), tcp("[::1]:38101"));
+    assert_eq!(DebugAddress::parse("unix:dbg.sock"), unix("dbg.sock"));
+    assert_eq!(DebugAddress::parse("./dbg.sock"), unix("./dbg.sock"));
+    assert_eq!(DebugAddress::parse("/tmp/dbg.sock"), unix("/tmp/dbg.sock"));
+    assert_eq!(DebugAddress::parse("dbg.sock"), tcp("dbg.sock"));
+
+    // Addresses are shown in a form, which parses to the same address
+    for address in &[DebugAddress::default(), unix("dbg.sock"), tcp("localhost:38101")] {
+        assert_eq!(&DebugAddress::parse(&address.to_string()), address);
+    }
+    assert_eq!(DebugAddress::default().to_string(), "127.0.0.1:38100");
+}
@@ -0,0 +1,83 @@
+extern crate neoqo;
+mod common;
+use neoqo::{Diagnostic, Severity, Span};
+use common::tokenize;
+
+/// Creates a span.
+fn span(line: u32, pos: u32, len: u32) -> Span {
+    Span {
+        line: line,
+        pos: pos,
+        len: len,
+    }
+}
+
+#[test]
+fn lexer_reports_unterminated_strings() {
+    let source = "+\n  \"abc";
+    let lexer = tokenize(source);
+    assert!(lexer.has_errors());
+    assert_eq!(lexer.diagnostics,
+               vec![Diagnostic::new(Severity::Error,
+                                    "Unterminated string literal".to_string(),
+                                    span(1, 2, 4))]);
+    assert!(lexer.diagnostics[0].render(source).ends_with("  |   \"abc\n  |   ^^^^\n"));
+}
+
+#[test]
+fn lexer_reports_invalid_escapes() {
+    let lexer = tokenize("+\"a\\qb\"");
+    assert!(lexer.has_errors());
+    assert_eq!(lexer.diagnostics,
+               vec![Diagnostic::new(Severity::Error,
+                                    "Invalid escape sequence '\\q'".to_string(),
+                                    span(0, 3, 2))]);
+    assert_eq!(lexer.tokens[1].value, "ab");
+}
+
+#[test]
+fn lexer_warns_about_lookalike_characters() {
+    let lexer = tokenize("+\n{-}\n'{ in a comment");
+    assert!(!lexer.has_errors());
+    assert_eq!(lexer.tokens.len(), 2);
+    assert_eq!(lexer.diagnostics,
+               vec![Diagnostic::new(Severity::Warning,
+                                    "Unknown character '{', did you mean '['?".to_string(),
+                                    span(1, 0, 1)),
+                    Diagnostic::new(Severity::Warning,
+                                    "Unknown character '}', did you mean ']'?".to_string(),
+                                    span(1, 2, 1))]);
+}
+
+#[test]
+fn lexer_positions_instructions_on_their_lines() {
+    let lexer = tokenize("+ >\n\t-");
+    let positions: Vec<_> = lexer.tokens.iter().map(|instr| (instr.line, instr.pos)).collect();
+    assert_eq!(positions, vec![(0, 0), (0, 2), (1, 1)]);
+}
+
+#[test]
+fn lexer_decodes_escape_sequences() {
+    let lexer = tokenize("\"\\\"q\\\" \\\\ \\0\\n\\r\\t \\x41\\x7e \\u{48}\\u{e9}\\u{1F600}\"");
+    assert!(lexer.diagnostics.is_empty(), "{:?}", lexer.diagnostics);
+    assert_eq!(lexer.tokens.len(), 1);
+    assert_eq!(lexer.tokens[0].value, "\"q\" \\ \0\n\r\t A~ H\u{e9}\u{1F600}");
+}
+
+#[test]
+fn lexer_rejects_malformed_escape_sequences() {
+    for &(source, escape) in &[("\"\\x4\"", "\\x4"),
+                               ("\"\\xg1\"", "\\x"),
+                               ("\"\\u{}\"", "\\u{"),
+                               ("\"\\u{110000}\"", "\\u{110000}"),
+                               ("\"\\u{D800}\"", "\\u{D800}"),
+                               ("\"\\u{1234567}\"", "\\u{123456"),
+                               ("\"\\u41\"", "\\u")] {
+        let lexer = tokenize(source);
+        let messages: Vec<_> = lexer.diagnostics.iter().map(|d| d.message.clone()).collect();
+        assert_eq!(messages,
+                   vec![format!("Invalid escape sequence '{}'", escape)],
+                   "{}",
+                   source);
+    }
+}
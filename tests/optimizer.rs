@@ -0,0 +1,370 @@
+extern crate neoqo;
+mod common;
+use std::io::Cursor;
+use neoqo::{Optimizer, OptimizerPass, OptimizationLevel, VirtualMachine, SharedBuffer};
+use neoqo::{CellConfig, CellWidth, Overflow, TapeEdge, ExecutionLimits, Instruction, Opcode};
+use neoqo::{Validation, Limit};
+use common::lex;
+
+/// The tick limit of the programs ran by the tests.
+const MAX_TICKS: u64 = 1_000_000;
+
+/// The `Outcome` type.
+///
+/// The observable behaviour of a program.
+#[derive(PartialEq, Debug)]
+struct Outcome {
+    /// The output.
+    output: String,
+
+    /// The kind of the error, if any.
+    error: Option<String>,
+}
+
+/// Tokenizes and optimizes the source using the passes.
+fn optimize(source: &str, passes: Vec<OptimizerPass>, cell_config: CellConfig) -> Vec<Instruction> {
+    let mut optimizer = Optimizer::new(lex(source), Some(passes));
+    optimizer.set_cell_config(cell_config);
+    optimizer.optimize();
+    optimizer.instructions
+}
+
+/// Runs the instructions and records their behaviour.
+fn run(instructions: Vec<Instruction>, cell_config: CellConfig) -> Outcome {
+    let output = SharedBuffer::new();
+    let mut vm = VirtualMachine::new(instructions, None, None);
+    vm.set_cell_config(cell_config);
+    vm.set_limits(ExecutionLimits { max_ticks: Some(MAX_TICKS), ..ExecutionLimits::new() });
+    vm.set_input(Box::new(Cursor::new(Vec::new())));
+    vm.set_output(Box::new(output.clone()));
+    let error = vm.run().err().map(|err| {
+        let kind = format!("{:?}", err);
+        kind[..kind.find('(').unwrap_or(kind.len())].to_string()
+    });
+    Outcome {
+        output: String::from_utf8_lossy(&output.contents()).into_owned(),
+        error: error,
+    }
+}
+
+/// Asserts that every optimization level and every single pass
+/// preserves the behaviour of the program.
+fn assert_equivalent(source: &str, cell_config: CellConfig) {
+    let expected = run(lex(source), cell_config);
+    let levels = (1..4).map(|n| OptimizationLevel::from_number(n).unwrap().passes());
+    let passes = OptimizerPass::all().iter().map(|&pass| vec![pass]);
+    for passes in levels.chain(passes) {
+        assert_preserves(source, passes, cell_config, &expected);
+    }
+}
+
+/// Asserts that the passes preserve the behaviour of the program.
+fn assert_preserves(source: &str,
+                    passes: Vec<OptimizerPass>,
+                    cell_config: CellConfig,
+                    expected: &Outcome) {
+    let outcome = run(optimize(source, passes.clone(), cell_config), cell_config);
+    assert_eq!(&outcome, expected, "{} using {:?}", source, passes);
+}
+
+/// Tests if the pass rewrote the source to an instruction with the opcode.
+fn rewrites_to(source: &str, pass: OptimizerPass, opcode: Opcode) -> bool {
+    rewrites_to_using(source, pass, opcode, CellConfig::new())
+}
+
+/// Tests if the pass rewrote the source to an instruction with the opcode,
+/// optimizing for the cell configuration.
+fn rewrites_to_using(source: &str,
+                     pass: OptimizerPass,
+                     opcode: Opcode,
+                     cell_config: CellConfig)
+                     -> bool {
+    optimize(source, vec![pass], cell_config).iter().any(|instr| instr.is(opcode))
+}
+
+/// Creates a cell configuration.
+fn config(width: CellWidth, overflow: Overflow, edge: TapeEdge) -> CellConfig {
+    CellConfig {
+        width: width,
+        overflow: overflow,
+        edge: edge,
+    }
+}
+
+#[test]
+fn dead_code_removes_loops_which_are_never_entered() {
+    let source = "[-]+[-][+.]i.";
+    assert_equivalent(source, CellConfig::new());
+    let instructions = optimize(source, vec![OptimizerPass::OptimizeDeadCode], CellConfig::new());
+    assert_eq!(instructions.len(), 6);
+}
+
+#[test]
+fn dead_code_removes_instructions_after_terminate_and_break() {
+    let source = "+[!+.]q+.";
+    assert_equivalent(source, CellConfig::new());
+    let instructions = optimize(source, vec![OptimizerPass::OptimizeDeadCode], CellConfig::new());
+    assert_eq!(instructions.len(), 5);
+}
+
+#[test]
+fn dead_code_keeps_programs_which_cannot_be_loaded() {
+    for &source in &["q!", "+q^", "q]", "q(", "+[q]!"] {
+        assert_equivalent(source, CellConfig::new());
+        assert_eq!(optimize(source, OptimizerPass::all().to_vec(), CellConfig::new()),
+                   lex(source));
+    }
+}
+
+#[test]
+fn dead_code_keeps_loops_after_a_loop_with_break() {
+    assert_equivalent("+[!][i.-]", CellConfig::new());
+    assert_equivalent("+[[!]!][i.-]", CellConfig::new());
+    assert_equivalent("\"ab\"(!)(;i.)", CellConfig::new());
+    assert!(rewrites_to("+[!][i.-]", OptimizerPass::OptimizeDeadCode, Opcode::Print));
+    assert!(!rewrites_to("+[[!]][i.-]", OptimizerPass::OptimizeDeadCode, Opcode::Print));
+}
+
+#[test]
+fn constants_fold_known_cell_values() {
+    let source = "++**++i.[-]++**++i.";
+    assert_equivalent(source, CellConfig::new());
+    let instructions = optimize(source, vec![OptimizerPass::OptimizeConstants], CellConfig::new());
+    let sets: Vec<_> = instructions.iter().filter(|instr| instr.is(Opcode::Set)).collect();
+    assert_eq!(sets.len(), 2);
+    assert!(sets.iter().all(|instr| instr.argument == Some(10)));
+}
+
+#[test]
+fn constants_stop_at_values_out_of_range() {
+    assert_equivalent("++++++++++++++++****.", CellConfig::new());
+    assert_equivalent("+*********.", CellConfig::new());
+}
+
+#[test]
+fn constants_keep_values_after_a_loop_with_break() {
+    assert_equivalent("+[!]++*i.", CellConfig::new());
+    assert!(!rewrites_to("+[!]++*i.", OptimizerPass::OptimizeConstants, Opcode::Set));
+    assert!(rewrites_to("+[-]++*i.", OptimizerPass::OptimizeConstants, Opcode::Set));
+}
+
+#[test]
+fn multiply_loops_move_cell_values() {
+    let source = "+++++[->++>+++<<]>i.>.<<+++[->>-<<]>>.";
+    assert_equivalent(source, CellConfig::new());
+    assert_equivalent(source, config(CellWidth::U8, Overflow::Wrap, TapeEdge::Error));
+    assert!(rewrites_to(source, OptimizerPass::OptimizeMultiplyLoops, Opcode::MulAdd));
+    assert!(rewrites_to(source, OptimizerPass::OptimizeMultiplyLoops, Opcode::MulSub));
+}
+
+#[test]
+fn multiply_loops_do_nothing_for_a_zero_cell() {
+    let source = format!("{}[->+<]i.", ">".repeat(127));
+    for &edge in &[TapeEdge::Error, TapeEdge::Grow] {
+        let cell_config = config(CellWidth::U32, Overflow::Saturate, edge);
+        assert_equivalent(&source, cell_config);
+        assert!(rewrites_to_using(&source,
+                                  OptimizerPass::OptimizeMultiplyLoops,
+                                  Opcode::MulAdd,
+                                  cell_config));
+    }
+}
+
+#[test]
+fn multiply_loops_respect_the_cell_configuration() {
+
+    // A loop starting at a negative cell never ends
+    let cell_config = config(CellWidth::I64, Overflow::Saturate, TapeEdge::Grow);
+    assert_equivalent("-[->+<]>i.", cell_config);
+    assert!(!rewrites_to_using("-[->+<]",
+                               OptimizerPass::OptimizeMultiplyLoops,
+                               Opcode::MulAdd,
+                               cell_config));
+
+    // Clamped pointer movement does not add up
+    let source = format!("{}++[->+<]i.", ">".repeat(127));
+    let cell_config = config(CellWidth::U32, Overflow::Saturate, TapeEdge::Clamp);
+    assert!(!rewrites_to_using(&source,
+                               OptimizerPass::OptimizeMultiplyLoops,
+                               Opcode::MulAdd,
+                               cell_config));
+    assert_equivalent(&source, cell_config);
+
+    // Saturating in the middle of an iteration differs from saturating the net change
+    let source = format!(">{}<+++[->++-<]>i.", "+".repeat(254));
+    for &overflow in &[Overflow::Saturate, Overflow::Wrap] {
+        let cell_config = config(CellWidth::U8, overflow, TapeEdge::Grow);
+        let expected = run(lex(&source), cell_config);
+        assert_preserves(&source,
+                         vec![OptimizerPass::OptimizeMultiplyLoops],
+                         cell_config,
+                         &expected);
+    }
+}
+
+#[test]
+fn offsets_address_cells_relative_to_the_cell_pointer() {
+    let source = "+>++>+++<<[>i.<-]>>.<.<:>>;i.";
+    assert_equivalent(source, CellConfig::new());
+    let instructions = optimize(source, vec![OptimizerPass::OptimizeOffsets], CellConfig::new());
+    assert!(instructions.iter().any(|instr| instr.offset != 0));
+    assert!(instructions.len() < lex(source).len());
+}
+
+#[test]
+fn offsets_respect_the_tape_edge() {
+    let source = "<+>i.>+<<<.";
+    for &edge in &[TapeEdge::Error, TapeEdge::Clamp, TapeEdge::Wrap, TapeEdge::Grow] {
+        assert_equivalent(source, config(CellWidth::U32, Overflow::Saturate, edge));
+    }
+
+    // Leaving the memory between two accesses is an error on a bounded tape
+    let source = format!("{}i<.", ">".repeat(128));
+    let cell_config = config(CellWidth::U32, Overflow::Saturate, TapeEdge::Error);
+    assert_equivalent(&source, cell_config);
+    assert_eq!(run(lex(&source), cell_config).error, Some("MemoryOutOfBounds".to_string()));
+}
+
+#[test]
+fn scan_loops_search_for_a_zero_cell() {
+    let source = "+>+>+>>+<<<<[>]+i.<[<]>i.";
+    assert_equivalent(source, CellConfig::new());
+    assert!(rewrites_to(source, OptimizerPass::OptimizeScanLoops, Opcode::ScanRight));
+    assert!(rewrites_to(source, OptimizerPass::OptimizeScanLoops, Opcode::ScanLeft));
+}
+
+#[test]
+fn scan_loops_move_in_steps() {
+    let source = "+>+>+>+>+>>+<<<<<<[>>]+i.<<[<<<]>i.";
+    assert_equivalent(source, CellConfig::new());
+    let passes = vec![OptimizerPass::OptimizeIncDecPtrChains, OptimizerPass::OptimizeScanLoops];
+    let instructions = optimize(source, passes, CellConfig::new());
+    let strides: Vec<_> = instructions.iter()
+        .filter(|instr| instr.is(Opcode::ScanLeft) || instr.is(Opcode::ScanRight))
+        .map(|instr| instr.argument)
+        .collect();
+    assert_eq!(strides, vec![Some(2), Some(3)]);
+}
+
+#[test]
+fn scan_loops_respect_the_tape_edge() {
+    for &source in &["+[<]>i.", "+>+>+[>]<i."] {
+        for &edge in &[TapeEdge::Error, TapeEdge::Clamp, TapeEdge::Wrap, TapeEdge::Grow] {
+            assert_equivalent(source, config(CellWidth::U32, Overflow::Saturate, edge));
+        }
+    }
+}
+
+#[test]
+fn stack_operations_remove_pairs_which_do_nothing() {
+    let source = "+++:;i.\"ab\"\\\\;i.;i.";
+    assert_equivalent(source, CellConfig::new());
+    let instructions = optimize(source,
+                                vec![OptimizerPass::OptimizeStackOperations],
+                                CellConfig::new());
+    assert!(!instructions.iter().any(|instr| instr.is(Opcode::Push) || instr.is(Opcode::Swap)));
+}
+
+#[test]
+fn stack_operations_peek_at_the_top_value() {
+    let source = "+++:>&;i.<;i.#i.";
+    assert_equivalent(source, CellConfig::new());
+    assert!(rewrites_to(source, OptimizerPass::OptimizeStackOperations, Opcode::Peek));
+}
+
+#[test]
+fn print_strings_print_the_whole_string() {
+    let source = "c\"hello\"(;.);i.";
+    assert_equivalent(source, CellConfig::new());
+    assert_equivalent(source, config(CellWidth::U8, Overflow::Error, TapeEdge::Grow));
+    assert!(rewrites_to(source, OptimizerPass::OptimizePrintStrings, Opcode::PrintStr));
+}
+
+#[test]
+fn print_strings_keep_strings_containing_zero() {
+    let source = "c\"a\\x00b\"(;.);i.";
+    assert_equivalent(source, CellConfig::new());
+    assert!(!rewrites_to(source, OptimizerPass::OptimizePrintStrings, Opcode::PrintStr));
+}
+
+#[test]
+fn validation_reports_where_the_behaviour_differs() {
+    // Optimizing for a growing tape, but running on a clamped one
+    let passes = vec![OptimizerPass::OptimizeOffsets];
+    let mut optimizer = Optimizer::new(lex("+++\n<+>i."), Some(passes));
+    let divergence = optimizer.optimize_validated(&[], |vm| {
+            vm.set_cell_config(config(CellWidth::U32, Overflow::Saturate, TapeEdge::Clamp));
+        })
+        .unwrap_err();
+    assert_eq!(divergence.pass, OptimizerPass::OptimizeOffsets);
+    assert_eq!((divergence.instr.line, divergence.instr.pos), (1, 0));
+    assert_eq!(divergence.position, (1, 4));
+    assert_eq!(divergence.reason, "Output differs at byte 0");
+    assert_eq!(optimizer.instructions, lex("+++\n<+>i."));
+}
+
+#[test]
+fn validation_is_skipped_for_programs_exceeding_a_limit() {
+    let source = "+++[+-]";
+    let mut optimizer = Optimizer::new(lex(source), Some(OptimizerPass::all().to_vec()));
+    let validation = optimizer.optimize_validated(&[], |vm| {
+        vm.set_limits(ExecutionLimits { max_ticks: Some(1000), ..ExecutionLimits::new() });
+    });
+    assert_eq!(validation, Ok(Validation::Skipped(Limit::Ticks)));
+    assert!(optimizer.instructions.len() < lex(source).len());
+
+    // Programs within the limits are validated
+    let mut optimizer = Optimizer::new(lex("+++[-]i."), Some(OptimizerPass::all().to_vec()));
+    assert_eq!(optimizer.optimize_validated(&[], |_| ()), Ok(Validation::Validated));
+}
+
+#[test]
+fn passes_run_until_nothing_changes() {
+    let passes = OptimizationLevel::Basic.passes();
+    let mut optimizer = Optimizer::new(lex("+>>+<<[-]i."), Some(passes.clone()));
+    optimizer.optimize();
+    assert_eq!(optimizer.iterations(), 2);
+    assert_eq!(optimizer.instructions.len(), 7);
+    let statistics: Vec<_> = optimizer.statistics()
+        .iter()
+        .map(|stats| (stats.pass, stats.runs, stats.rewrites, stats.removed))
+        .collect();
+    assert_eq!(statistics,
+               vec![(OptimizerPass::OptimizeIncDecPtrChains, 2, 2, 2),
+                    (OptimizerPass::OptimizeIncDecValChains, 2, 0, 0),
+                    (OptimizerPass::OptimizeClearLoops, 2, 1, 2)]);
+
+    // The iterations are limited
+    let mut optimizer = Optimizer::new(lex("+>>+<<[-]i."), Some(passes));
+    optimizer.set_max_iterations(1);
+    optimizer.optimize();
+    assert_eq!(optimizer.iterations(), 1);
+    assert!(optimizer.statistics().iter().all(|statistics| statistics.runs == 1));
+}
+
+#[test]
+fn optimization_levels_select_their_passes() {
+    let level = |n| OptimizationLevel::from_number(n).unwrap();
+    assert_eq!(level(0).passes(), vec![]);
+    assert_eq!(level(1).passes(),
+               vec![OptimizerPass::OptimizeIncDecPtrChains,
+                    OptimizerPass::OptimizeIncDecValChains,
+                    OptimizerPass::OptimizeClearLoops]);
+    let default: Vec<_> = OptimizerPass::all()
+        .iter()
+        .cloned()
+        .filter(|&pass| pass != OptimizerPass::OptimizeStackOperations)
+        .collect();
+    assert_eq!(level(2).passes(), default);
+    assert_eq!(level(3).passes(), OptimizerPass::all().to_vec());
+    assert_eq!(OptimizationLevel::from_number(4), None);
+    assert_eq!(OptimizationLevel::default(), level(2));
+}
+
+#[test]
+fn passes_are_found_by_their_names() {
+    for &pass in OptimizerPass::all() {
+        assert_eq!(OptimizerPass::from_name(pass.name()), Some(pass));
+    }
+    assert_eq!(OptimizerPass::from_name("unknown"), None);
+}
@@ -0,0 +1,58 @@
+extern crate neoqo;
+use neoqo::Tape;
+
+#[test]
+fn dense_tapes_grow_in_both_directions() {
+    let mut tape = Tape::new(4);
+    assert!(!tape.is_sparse());
+    assert_eq!((tape.low(), tape.high(), tape.len()), (0, 4, 4));
+
+    // Every cell between the bounds is stored
+    assert!(tape.set(-2, 1));
+    assert!(tape.set(5, 2));
+    assert_eq!((tape.low(), tape.high()), (-2, 6));
+    assert_eq!(tape.allocated(), 8);
+    assert_eq!(tape.allocated_with(9), Some(12));
+    assert_eq!(tape.window(-3, 7), vec![0, 1, 0, 0, 0, 0, 0, 0, 2, 0]);
+    assert_eq!(tape.cells(), vec![(-2, 1), (5, 2)]);
+
+    // Reading cells does not grow the tape
+    assert_eq!(tape.get(100), 0);
+    assert!(!tape.contains(100));
+}
+
+#[test]
+fn sparse_tapes_only_store_non_zero_cells() {
+    let mut tape = Tape::sparse(4);
+    assert!(tape.is_sparse());
+    assert_eq!(tape.allocated(), 0);
+
+    // Growing the tape is free
+    assert!(tape.set(-(1 << 61), 1));
+    assert!(tape.set(1 << 61, 2));
+    assert_eq!((tape.low(), tape.high()), (-(1 << 61), (1 << 61) + 1));
+    assert_eq!(tape.allocated(), 2);
+    assert_eq!(tape.allocated_with(1 << 62), Some(2));
+
+    // Clearing a cell removes it
+    assert!(tape.set(1 << 61, 0));
+    assert_eq!(tape.allocated(), 1);
+    assert_eq!(tape.cells(), vec![(-(1 << 61), 1)]);
+    assert_eq!(tape.window(-1, 2), vec![0, 0, 0]);
+}
+
+#[test]
+fn tapes_reject_cells_they_cannot_hold() {
+    // The number of cells would not fit into an `i64`
+    let mut tape = Tape::new(4);
+    assert_eq!(tape.allocated_with(i64::max_value()), None);
+    assert!(!tape.grow(i64::max_value()));
+    assert!(!tape.set(i64::min_value(), 1));
+    assert_eq!((tape.low(), tape.high()), (0, 4));
+
+    let mut tape = Tape::sparse(4);
+    assert!(tape.set(-(1 << 61), 1));
+    assert!(!tape.set(i64::max_value(), 1));
+    assert_eq!(tape.get(i64::max_value()), 0);
+    assert_eq!(tape.allocated(), 1);
+}
@@ -0,0 +1,256 @@
+extern crate neoqo;
+mod common;
+use std::io::Cursor;
+use std::thread;
+use neoqo::{VirtualMachine, SharedBuffer, ExecutionLimits, Limit, VmError};
+use neoqo::{CellConfig, CellWidth, Optimizer, OptimizerPass, Opcode, ExecutionStatus};
+use neoqo::PrintMode;
+use common::lex;
+
+/// Creates a virtual machine running the source on the input.
+fn create(source: &str, input: &[u8], output: &SharedBuffer) -> VirtualMachine {
+    let mut vm = VirtualMachine::new(lex(source), None, None);
+    vm.set_cell_config(CellConfig { width: CellWidth::I64, ..CellConfig::new() });
+    vm.set_input(Box::new(Cursor::new(input.to_vec())));
+    vm.set_output(Box::new(output.clone()));
+    vm
+}
+
+#[test]
+fn read_does_not_allocate_the_requested_size_up_front() {
+    let source = format!("+{}:,#i.", "*".repeat(62));
+    let output = SharedBuffer::new();
+    let mut vm = create(&source, b"abc", &output);
+    assert_eq!(vm.run().ok(), Some(0));
+    assert_eq!(output.contents(), b"4");
+}
+
+#[test]
+fn read_tests_the_stack_limit_before_reading() {
+    let source = format!("+{}:,", "*".repeat(62));
+    let output = SharedBuffer::new();
+    let mut vm = create(&source, b"abc", &output);
+    vm.set_limits(ExecutionLimits { max_stack_depth: Some(64), ..ExecutionLimits::new() });
+    match vm.run() {
+        Err(VmError::LimitExceeded(_, Limit::StackDepth)) => (),
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert_eq!(vm.stack().len(), 1);
+}
+
+#[test]
+fn pushing_tests_the_stack_limit_before_pushing() {
+    let output = SharedBuffer::new();
+    let mut vm = create("\"abc\"&", b"", &output);
+    vm.set_limits(ExecutionLimits { max_stack_depth: Some(4), ..ExecutionLimits::new() });
+    match vm.run() {
+        Err(VmError::LimitExceeded(ctx, Limit::StackDepth)) => assert_eq!(ctx.ip, 1),
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert_eq!(vm.stack().len(), 4);
+}
+
+#[test]
+fn writing_after_the_program_halted_reports_errors() {
+    let output = SharedBuffer::new();
+    for &source in &["+:", ""] {
+        let mut vm = create(source, b"", &output);
+        assert_eq!(vm.run().ok(), Some(0));
+        match vm.write_cell(i64::max_value(), 1) {
+            Err(VmError::LimitExceeded(ctx, Limit::Memory)) => {
+                assert_eq!(ctx.instr, None);
+                assert_eq!(ctx.ip, vm.instructions().len());
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+        match vm.write_stack(1, 1) {
+            Err(err @ VmError::StackUnderflow(_)) => {
+                assert!(err.to_string().contains("the end of the program"));
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn virtual_machines_run_on_other_threads() {
+    let output = SharedBuffer::new();
+    let mut vm = create("+++i.", b"", &output);
+    let result = thread::spawn(move || vm.run().ok()).join().unwrap();
+    assert_eq!(result, Some(0));
+    assert_eq!(output.contents(), b"3");
+}
+
+#[test]
+fn buffered_runs_keep_the_output_of_failed_programs() {
+    match VirtualMachine::run_buffered(lex("+i.\\\\"), b"") {
+        (ref output, Err(VmError::StackUnderflow(_))) => assert_eq!(output, b"1"),
+        (output, result) => panic!("Unexpected result {:?} {:?}", output, result),
+    }
+}
+
+#[test]
+fn run_for_pauses_scans_partway() {
+    let source = format!("{}<[<]i.", "+>".repeat(100));
+    let reference = SharedBuffer::new();
+    let mut expected = create(&source, b"", &reference);
+    assert_eq!(expected.run().ok(), Some(0));
+
+    // Scan across the cells in small portions
+    let mut optimizer = Optimizer::new(expected.instructions().to_vec(),
+                                       Some(vec![OptimizerPass::OptimizeScanLoops]));
+    optimizer.optimize();
+    assert!(optimizer.instructions.iter().any(|instr| instr.opcode == Opcode::ScanLeft));
+    let output = SharedBuffer::new();
+    let mut vm = VirtualMachine::new(optimizer.instructions, None, None);
+    vm.set_output(Box::new(output.clone()));
+    let mut calls = 0;
+    loop {
+        let ticks = vm.ticks();
+        let status = vm.run_for(10).unwrap();
+        assert!(vm.ticks() - ticks <= 11, "{} ticks", vm.ticks() - ticks);
+        calls += 1;
+        if status == ExecutionStatus::Halted(0) {
+            break;
+        }
+    }
+    assert!(calls > 10);
+    assert_eq!(vm.cp(), expected.cp());
+    assert_eq!(output.contents(), reference.contents());
+}
+
+#[test]
+fn unmatched_loops_are_reported() {
+    // The innermost loop, which is not closed, is reported
+    for &(source, ip) in &[("+[", 1), ("[+(-)", 0), ("+]", 1), ("+)(", 1)] {
+        let output = SharedBuffer::new();
+        match create(source, b"", &output).run() {
+            Err(VmError::UnmatchedLoop(ctx)) => assert_eq!(ctx.ip, ip, "{}", source),
+            result => panic!("Unexpected result {:?} for {}", result, source),
+        }
+    }
+}
+
+#[test]
+fn loop_controls_outside_of_loops_are_reported() {
+    for &(source, ip) in &[("!", 0), ("+:^", 2), ("+[-]!", 4)] {
+        let output = SharedBuffer::new();
+        match create(source, b"", &output).run() {
+            Err(VmError::OutsideOfLoop(ctx)) => assert_eq!(ctx.ip, ip, "{}", source),
+            result => panic!("Unexpected result {:?} for {}", result, source),
+        }
+    }
+}
+
+#[test]
+fn stack_underflows_are_reported() {
+    for &(source, ip) in &[("&", 0), (":\\", 1), ("+:=", 2), ("+[^]", 2)] {
+        let output = SharedBuffer::new();
+        let mut vm = create(source, b"", &output);
+        match vm.run() {
+            Err(VmError::StackUnderflow(ctx)) => {
+                assert_eq!(ctx.ip, ip, "{}", source);
+                assert_eq!(ctx.instr.map(|instr| instr.opcode),
+                           vm.instructions().get(ip).map(|instr| instr.opcode));
+            }
+            result => panic!("Unexpected result {:?} for {}", result, source),
+        }
+    }
+}
+
+#[test]
+fn terminate_exits_with_the_cell_value() {
+    let output = SharedBuffer::new();
+    let mut vm = create("+++q+i.", b"", &output);
+    assert_eq!(vm.run().ok(), Some(3));
+    assert_eq!(vm.status(), ExecutionStatus::Halted(3));
+    assert_eq!(output.contents(), b"");
+}
+
+#[test]
+fn loop_controls_leave_or_continue_the_loop() {
+    // `!` continues behind the end of the loop
+    let output = SharedBuffer::new();
+    assert_eq!(create("+[+++!+]i.", b"", &output).run().ok(), Some(0));
+    assert_eq!(output.contents(), b"4");
+
+    // `^` pops the top value and continues at the end of the loop, if it is not zero
+    let output = SharedBuffer::new();
+    let mut vm = create("+++[-:^i.]", b"", &output);
+    assert_eq!(vm.run().ok(), Some(0));
+    assert_eq!(output.contents(), b"0");
+    assert!(vm.stack().is_empty());
+}
+
+#[test]
+fn print_modes_format_the_cell_value() {
+    let source = format!("{}.b.x.i.", "+".repeat(255));
+    let output = SharedBuffer::new();
+    assert_eq!(create(&source, b"", &output).run().ok(), Some(0));
+    assert_eq!(output.contents(), "\u{ff}11111111ff255".as_bytes());
+}
+
+#[test]
+fn step_executes_one_instruction_at_a_time() {
+    let output = SharedBuffer::new();
+    let mut vm = create("++i.", b"", &output);
+    assert_eq!(vm.status(), ExecutionStatus::Running);
+    assert_eq!(vm.step().ok(), Some(ExecutionStatus::Running));
+    assert_eq!((vm.ip(), vm.ticks()), (1, 1));
+    assert_eq!(vm.run_for(2).ok(), Some(ExecutionStatus::Running));
+    assert_eq!((vm.ip(), vm.ticks()), (3, 3));
+    assert_eq!(output.contents(), b"");
+    assert_eq!(vm.step().ok(), Some(ExecutionStatus::Halted(0)));
+    assert_eq!(output.contents(), b"2");
+
+    // Halted programs stay halted
+    assert_eq!(vm.step().ok(), Some(ExecutionStatus::Halted(0)));
+    assert_eq!(vm.run_for(10).ok(), Some(ExecutionStatus::Halted(0)));
+    assert_eq!(vm.ticks(), 4);
+}
+
+#[test]
+fn run_until_stops_before_the_instruction_the_predicate_accepts() {
+    let output = SharedBuffer::new();
+    let mut vm = create("+++[-]++q", b"", &output);
+    let status = vm.run_until(|vm| {
+        vm.current_instruction().map_or(false, |instr| instr.opcode == Opcode::Dec)
+    });
+    assert_eq!(status.ok(), Some(ExecutionStatus::Running));
+    assert_eq!((vm.ip(), vm.tape().get(0)), (4, 3));
+
+    // The predicate is tested before every instruction
+    let status = vm.run_until(|vm| vm.tape().get(vm.cp()) == 0);
+    assert_eq!(status.ok(), Some(ExecutionStatus::Running));
+    assert_eq!(vm.ip(), 5);
+
+    // Stops once the program halts
+    assert_eq!(vm.run_until(|_| false).ok(), Some(ExecutionStatus::Halted(2)));
+}
+
+#[test]
+fn the_state_can_be_inspected_between_instructions() {
+    let output = SharedBuffer::new();
+    let mut vm = create("+>++:x<[-]", b"", &output);
+    assert!(vm.jump_table().is_empty());
+    assert_eq!(vm.run_for(5).ok(), Some(ExecutionStatus::Running));
+    assert_eq!((vm.ip(), vm.cp(), vm.ticks()), (5, 1, 5));
+    assert_eq!(vm.current_instruction().map(|instr| instr.opcode), Some(Opcode::HexMod));
+    assert_eq!(vm.tape().cells(), vec![(0, 1), (1, 2)]);
+    assert_eq!(vm.stack(), &[2]);
+    assert_eq!(vm.print_mode(), PrintMode::Char);
+    assert_eq!(vm.jump_table().iter().map(|(&a, &b)| (a, b)).collect::<Vec<_>>(),
+               vec![(7, 9), (9, 7)]);
+
+    // Snapshots own a copy of the state
+    let snapshot = vm.snapshot();
+    assert_eq!(vm.run().ok(), Some(0));
+    assert_eq!((snapshot.ip, snapshot.cp, snapshot.exit_code), (5, 1, None));
+    assert_eq!(snapshot.memory, vec![(0, 1), (1, 2)]);
+    assert_eq!(snapshot.instr.map(|instr| instr.opcode), Some(Opcode::HexMod));
+
+    let snapshot = vm.snapshot();
+    assert_eq!((snapshot.cp, snapshot.exit_code, snapshot.instr), (0, Some(0), None));
+    assert_eq!((snapshot.memory, snapshot.stack), (vec![(1, 2)], vec![2]));
+    assert_eq!(snapshot.print_mode, PrintMode::Hexadecimal);
+}